@@ -0,0 +1,63 @@
+use std::env;
+use std::process;
+
+extern crate goldberg;
+use goldberg::engine::*;
+use goldberg::obj::*;
+use goldberg::decomposition::*;
+
+// Replace a primitive's colliders with a set of convex hulls built from its .obj
+// For concave primitives (bowls, channels etc) where a single hull would fill the hollow
+// Usage: decompose-primitive [--assets <assets dir>] [--resolution <voxels>] [--max-hulls <count>] <primitive.json>
+fn main() {
+    let mut assets_path = String::from("assets/");
+    let mut settings = DecompositionSettings::default();
+    let mut primitive_file = None;
+    let mut arg_it = env::args().skip(1);
+    while let Some(arg) = arg_it.next() {
+        match arg.as_str() {
+            "--assets" => assets_path = arg_it.next().unwrap_or(assets_path),
+            "--resolution" => settings.resolution = arg_it.next().and_then(|x| x.parse().ok()).unwrap_or(settings.resolution),
+            "--max-hulls" => settings.max_hulls = arg_it.next().and_then(|x| x.parse().ok()).unwrap_or(settings.max_hulls),
+            _ => primitive_file = Some(arg),
+        }
+    }
+    let primitive_file = match primitive_file {
+        Some(x) => x,
+        None => {
+            println!("Usage: decompose-primitive [--assets <assets dir>] [--resolution <voxels>] [--max-hulls <count>] <primitive.json>");
+            process::exit(1);
+        }
+    };
+
+    let mut prim = match load_primitive_definition(&primitive_file) {
+        Ok(x) => x,
+        Err(e) => {
+            println!("ERROR: Failed to load primitive: {}", e);
+            process::exit(1);
+        }
+    };
+    let mesh = match load_obj(&format!("{}/{}", assets_path, prim.path_obj)) {
+        Ok(x) => x,
+        Err(e) => {
+            println!("ERROR: Failed to load mesh: {}", e);
+            process::exit(1);
+        }
+    };
+
+    let colliders = decompose(&mesh, &settings);
+    if colliders.is_empty() {
+        println!("ERROR: Couldn't decompose {}, is the mesh closed?", prim.path_obj);
+        process::exit(1);
+    }
+    for (i, c) in colliders.iter().enumerate() {
+        println!("Hull {}: {} points", i, c.points.len());
+    }
+    prim.collider_def = colliders;
+
+    if let Err(e) = save_primitive_definition(&prim, &primitive_file) {
+        println!("ERROR: Failed to save primitive: {}", e);
+        process::exit(1);
+    }
+    println!("Saved {} convex hulls to {}", prim.collider_def.len(), primitive_file);
+}
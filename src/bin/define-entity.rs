@@ -3,12 +3,22 @@ use text_io::read;
 
 extern crate goldberg;
 use goldberg::types::*;
+use goldberg::migration::*;
 
 fn main() {
     let mut ent = EntityDefinition {
+        format_version : ENTITY_FORMAT_VERSION,
         name : String::from(""),
-        entity_type : EntityType::Cannon,
+        entity_type : EntityType::Cannon {
+            spawn_point : [0.0;3],
+            spawn_velocity : [0.0;3],
+            projectile : String::from(""),
+            projectile_scale : [1.0;3],
+            ammo : 0,
+            fire_delay : 0.0,
+        },
         primitive : PrimitiveDefinition {
+            format_version : PRIMITIVE_FORMAT_VERSION,
             name : String::from(""),
             path_obj : String::from(""),
             path_mtl : String::from(""),
@@ -19,12 +29,6 @@ fn main() {
             collider_def : Vec::new(),
         },
         active_default : false,
-        cannon_spawn_point : [0.0;3],
-        cannon_spawn_force : [0.0;3],
-        cannon_projectile_name : String::from(""),
-        cannon_projectile_scale : [1.0;3],
-        cannon_ammo : 0,
-        cannon_fire_delay : 0.0,
     };
 
     println!("Defining an entity. First define the primitive for it:");
@@ -51,11 +55,16 @@ fn main() {
 
     loop {
         // TODO: This sucks, improve it
-        println!("Select index of collider type [0 = Cuboid, 1 = Ball]");
+        println!("Select index of collider type [0 = Cuboid, 1 = Ball, 2 = Capsule, 3 = Cylinder, 4 = Cone, 5 = ConvexHull, 6 = TriMesh (static only)]");
         let collider_type_index : u32 = read!("{}\n");
         let t = match collider_type_index {
             0 => ColliderType::Cuboid,
             1 => ColliderType::Ball,
+            2 => ColliderType::Capsule,
+            3 => ColliderType::Cylinder,
+            4 => ColliderType::Cone,
+            5 => ColliderType::ConvexHull,
+            6 => ColliderType::TriMesh,
             _ => {
                 println!("ERROR: You chose an invalid type index");
                 return;
@@ -65,7 +74,10 @@ fn main() {
         let mut collider_def = ColliderDefinition {
             collider_type : t,
             origin : [0.0;3],
+            rotation : [0.0;3],
             dimensions : [0.0;3],
+            points : Vec::new(),
+            triangles : Vec::new(),
         };
 
         println!("\n");
@@ -76,19 +88,50 @@ fn main() {
         println!("Collider origin z");
         collider_def.origin[2] = read!("{}\n");
 
-        println!("Collider scale x");
-        collider_def.dimensions[0] = read!("{}\n");
-        println!("Collider scale y");
-        collider_def.dimensions[1] = read!("{}\n");
-        println!("Collider scale z");
-        collider_def.dimensions[2] = read!("{}\n");
-
-        println!("Collider scale x");
-        collider_def.dimensions[0] = read!("{}\n");
-        println!("Collider scale y");
-        collider_def.dimensions[1] = read!("{}\n");
-        println!("Collider scale z");
-        collider_def.dimensions[2] = read!("{}\n");
+        println!("Collider rotation x (degrees)");
+        collider_def.rotation[0] = read!("{}\n");
+        println!("Collider rotation y (degrees)");
+        collider_def.rotation[1] = read!("{}\n");
+        println!("Collider rotation z (degrees)");
+        collider_def.rotation[2] = read!("{}\n");
+
+        match collider_def.collider_type {
+            ColliderType::Cuboid => {
+                println!("Collider half size x");
+                collider_def.dimensions[0] = read!("{}\n");
+                println!("Collider half size y");
+                collider_def.dimensions[1] = read!("{}\n");
+                println!("Collider half size z");
+                collider_def.dimensions[2] = read!("{}\n");
+            },
+            ColliderType::Ball => {
+                println!("Collider radius");
+                collider_def.dimensions[0] = read!("{}\n");
+            },
+            ColliderType::Capsule | ColliderType::Cylinder | ColliderType::Cone => {
+                println!("Collider radius");
+                collider_def.dimensions[0] = read!("{}\n");
+                println!("Collider half height (y)");
+                collider_def.dimensions[1] = read!("{}\n");
+            },
+            ColliderType::ConvexHull => {
+                println!("Number of hull points (0 to build the hull from the .obj)");
+                let num_points : u32 = read!("{}\n");
+                for i in 0..num_points {
+                    let mut p = [0.0;3];
+                    println!("Point {} x", i);
+                    p[0] = read!("{}\n");
+                    println!("Point {} y", i);
+                    p[1] = read!("{}\n");
+                    println!("Point {} z", i);
+                    p[2] = read!("{}\n");
+                    collider_def.points.push(p);
+                }
+            },
+            ColliderType::TriMesh => {
+                println!("Triangle mesh will be built from the .obj");
+            },
+        }
 
         ent.primitive.collider_def.push(collider_def);
 
@@ -103,45 +146,61 @@ fn main() {
 
     println!("\n\n Now define the entity");
     // TODO: This sucks, improve it
-    println!("Select index of entity type [0 = Cannon]");
-    let entity_type_index : u32 = read!("{}\n");
-    let t = match entity_type_index {
-        0 => EntityType::Cannon,
-        _ => {
-            println!("ERROR: You chose an invalid type index");
-            return;
-        }
-    };
-    ent.entity_type = t;
-
     println!("Name: ");
     ent.name = read!("{}\n");
 
     println!("Active by default? ");
     ent.active_default = read!("{}\n");
 
-    println!("Cannon: Spawn point x: ");
-    ent.cannon_spawn_point[0] = read!("{}\n");
-    println!("Cannon: Spawn point y: ");
-    ent.cannon_spawn_point[1] = read!("{}\n");
-    println!("Cannon: Spawn point z: ");
-    ent.cannon_spawn_point[2] = read!("{}\n");
-    
-    println!("Cannon: Projectile name: ");
-    ent.cannon_projectile_name = read!("{}\n");
-
-    println!("Cannon: projectile scale x: ");
-    ent.cannon_projectile_scale[0] = read!("{}\n");
-    println!("Cannon: projectile scale y: ");
-    ent.cannon_projectile_scale[1] = read!("{}\n");
-    println!("Cannon: projectile scale z: ");
-    ent.cannon_projectile_scale[2] = read!("{}\n");
-    
-    println!("Cannon: Ammo Amount: ");
-    ent.cannon_ammo = read!("{}\n");
-
-    println!("Cannon: Fire Delay: ");
-    ent.cannon_fire_delay = read!("{}\n");
+    println!("Select index of entity type [0 = Cannon, 1 = MotorSwitch]");
+    let entity_type_index : u32 = read!("{}\n");
+    ent.entity_type = match entity_type_index {
+        0 => {
+            let mut spawn_point = [0.0;3];
+            println!("Cannon: Spawn point x: ");
+            spawn_point[0] = read!("{}\n");
+            println!("Cannon: Spawn point y: ");
+            spawn_point[1] = read!("{}\n");
+            println!("Cannon: Spawn point z: ");
+            spawn_point[2] = read!("{}\n");
+
+            let mut spawn_velocity = [0.0;3];
+            println!("Cannon: Spawn velocity x (m/s): ");
+            spawn_velocity[0] = read!("{}\n");
+            println!("Cannon: Spawn velocity y (m/s): ");
+            spawn_velocity[1] = read!("{}\n");
+            println!("Cannon: Spawn velocity z (m/s): ");
+            spawn_velocity[2] = read!("{}\n");
+
+            println!("Cannon: Projectile name: ");
+            let projectile : String = read!("{}\n");
+
+            let mut projectile_scale = [1.0;3];
+            println!("Cannon: projectile scale x: ");
+            projectile_scale[0] = read!("{}\n");
+            println!("Cannon: projectile scale y: ");
+            projectile_scale[1] = read!("{}\n");
+            println!("Cannon: projectile scale z: ");
+            projectile_scale[2] = read!("{}\n");
+
+            println!("Cannon: Ammo Amount: ");
+            let ammo : u32 = read!("{}\n");
+
+            println!("Cannon: Fire Delay: ");
+            let fire_delay : f32 = read!("{}\n");
+
+            EntityType::Cannon { spawn_point, spawn_velocity, projectile, projectile_scale, ammo, fire_delay }
+        },
+        1 => {
+            // Joints belong to levels, so each placement picks one
+            println!("MotorSwitch: Joint is set per level with \"parameters\" : {{ \"joint\" : <index> }}");
+            EntityType::MotorSwitch { joint : None }
+        },
+        _ => {
+            println!("ERROR: You chose an invalid type index");
+            return;
+        }
+    };
 
     println!("Nice, here's your data:");
     
@@ -3,9 +3,13 @@ use text_io::read;
 
 extern crate goldberg;
 use goldberg::types::*;
+use goldberg::migration::*;
+use goldberg::obj::*;
+use goldberg::decomposition::*;
 
 fn main() {
     let mut prim = PrimitiveDefinition {
+        format_version : PRIMITIVE_FORMAT_VERSION,
         name : String::from(""),
         path_obj : String::from(""),
         path_mtl : String::from(""),
@@ -38,13 +42,53 @@ fn main() {
     println!("Primitive z scale: ");
     prim.scale[2] = read!("{}\n");
 
-    loop {
+    // Offer colliders fitted to the mesh, rather than working them out by hand
+    let mut define_manually = true;
+    match load_obj(&format!("assets/{}", prim.path_obj)) {
+        Ok(mesh) => {
+            if let Some((min, max)) = mesh.bounds() {
+                println!("\n");
+                println!("Mesh bounds (as exported, y up): min {:?} max {:?}", [min.x, min.y, min.z], [max.x, max.y, max.z]);
+                println!("Mesh size: {:?}", [max.x - min.x, max.y - min.y, max.z - min.z]);
+                println!("Mesh size after primitive scale: {:?}", [
+                    (max.x - min.x) * prim.scale[0], (max.y - min.y) * prim.scale[1], (max.z - min.z) * prim.scale[2]]);
+            }
+
+            println!("Select index of generated collider [0 = None, 1 = Cuboid (bounds), 2 = Ball (bounding sphere), 3 = ConvexHull, 4 = Convex decomposition (concave meshes)]");
+            let generated_index : u32 = read!("{}\n");
+            let generated : Vec<ColliderDefinition> = match generated_index {
+                1 => fit_cuboid_collider(&mesh).into_iter().collect(),
+                2 => fit_ball_collider(&mesh).into_iter().collect(),
+                3 => fit_convex_hull_collider(&mesh).into_iter().collect(),
+                4 => decompose(&mesh, &DecompositionSettings::default()),
+                _ => Vec::new(),
+            };
+            if !generated.is_empty() {
+                for collider_def in generated {
+                    println!("Generated collider: {}", serde_json::to_string(&collider_def).unwrap());
+                    prim.collider_def.push(collider_def);
+                }
+
+                println!("Add more colliders manually (y/n)?");
+                let choice : String = read!("{}\n");
+                define_manually = choice == "y";
+            }
+        },
+        Err(e) => println!("WARNING: Can't generate colliders, failed to load mesh: {}", e),
+    }
+
+    while define_manually {
         // TODO: This sucks, improve it
-        println!("Select index of collider type [0 = Cuboid, 1 = Ball]");
+        println!("Select index of collider type [0 = Cuboid, 1 = Ball, 2 = Capsule, 3 = Cylinder, 4 = Cone, 5 = ConvexHull, 6 = TriMesh (static only)]");
         let collider_type_index : u32 = read!("{}\n");
         let t = match collider_type_index {
             0 => ColliderType::Cuboid,
             1 => ColliderType::Ball,
+            2 => ColliderType::Capsule,
+            3 => ColliderType::Cylinder,
+            4 => ColliderType::Cone,
+            5 => ColliderType::ConvexHull,
+            6 => ColliderType::TriMesh,
             _ => {
                 println!("ERROR: You chose an invalid type index");
                 return;
@@ -54,7 +98,10 @@ fn main() {
         let mut collider_def = ColliderDefinition {
             collider_type : t,
             origin : [0.0;3],
+            rotation : [0.0;3],
             dimensions : [0.0;3],
+            points : Vec::new(),
+            triangles : Vec::new(),
         };
 
         println!("\n");
@@ -65,22 +112,56 @@ fn main() {
         println!("Collider origin z");
         collider_def.origin[2] = read!("{}\n");
 
-        println!("Collider scale x");
-        collider_def.dimensions[0] = read!("{}\n");
-        println!("Collider scale y");
-        collider_def.dimensions[1] = read!("{}\n");
-        println!("Collider scale z");
-        collider_def.dimensions[2] = read!("{}\n");
+        println!("Collider rotation x (degrees)");
+        collider_def.rotation[0] = read!("{}\n");
+        println!("Collider rotation y (degrees)");
+        collider_def.rotation[1] = read!("{}\n");
+        println!("Collider rotation z (degrees)");
+        collider_def.rotation[2] = read!("{}\n");
+
+        match collider_def.collider_type {
+            ColliderType::Cuboid => {
+                println!("Collider half size x");
+                collider_def.dimensions[0] = read!("{}\n");
+                println!("Collider half size y");
+                collider_def.dimensions[1] = read!("{}\n");
+                println!("Collider half size z");
+                collider_def.dimensions[2] = read!("{}\n");
+            },
+            ColliderType::Ball => {
+                println!("Collider radius");
+                collider_def.dimensions[0] = read!("{}\n");
+            },
+            ColliderType::Capsule | ColliderType::Cylinder | ColliderType::Cone => {
+                println!("Collider radius");
+                collider_def.dimensions[0] = read!("{}\n");
+                println!("Collider half height (y)");
+                collider_def.dimensions[1] = read!("{}\n");
+            },
+            ColliderType::ConvexHull => {
+                println!("Number of hull points (0 to build the hull from the .obj)");
+                let num_points : u32 = read!("{}\n");
+                for i in 0..num_points {
+                    let mut p = [0.0;3];
+                    println!("Point {} x", i);
+                    p[0] = read!("{}\n");
+                    println!("Point {} y", i);
+                    p[1] = read!("{}\n");
+                    println!("Point {} z", i);
+                    p[2] = read!("{}\n");
+                    collider_def.points.push(p);
+                }
+            },
+            ColliderType::TriMesh => {
+                println!("Triangle mesh will be built from the .obj");
+            },
+        }
 
         prim.collider_def.push(collider_def);
 
         println!("Add another collider (y/n)?");
         let choice : String = read!("{}\n");
-        if choice == "y" {
-            continue;
-        } else {
-            break;
-        }
+        define_manually = choice == "y";
     }
 
     println!("Nice, here's your data:");
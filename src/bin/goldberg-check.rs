@@ -0,0 +1,119 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+use std::collections::HashMap;
+
+extern crate goldberg;
+use goldberg::engine::*;
+use goldberg::migration::*;
+use goldberg::validation::*;
+
+// Check the asset library and levels for problems, prints one line per problem
+// With --upgrade any files from an older format version are re-saved in the current format
+// Usage: goldberg-check [--assets <assets dir>] [--upgrade] [level.json...]
+fn main() {
+    let mut assets_path = String::from("assets/");
+    let mut level_files = Vec::new();
+    let mut upgrade = false;
+    let mut arg_it = env::args().skip(1);
+    while let Some(arg) = arg_it.next() {
+        match arg.as_str() {
+            "--assets" => assets_path = arg_it.next().unwrap_or(assets_path),
+            "--upgrade" => upgrade = true,
+            _ => level_files.push(arg),
+        }
+    }
+
+    if upgrade {
+        let mut files = Vec::new();
+        for (dir, kind) in &[("primitives", FormatKind::Primitive), ("primitives_hidden", FormatKind::Primitive), ("entities", FormatKind::Entity)] {
+            if let Ok(entries) = fs::read_dir(Path::new(&assets_path).join(dir)) {
+                for entry in entries.flatten() {
+                    let path = entry.path();
+                    if path.extension().is_some_and(|x| x == "json") {
+                        files.push((path.to_string_lossy().to_string(), *kind));
+                    }
+                }
+            }
+        }
+        for level_file in &level_files {
+            files.push((level_file.clone(), FormatKind::Level));
+        }
+
+        for (file, kind) in &files {
+            match upgrade_file(file, *kind) {
+                Ok(true) => println!("Upgraded {}", file),
+                Ok(false) => {},
+                Err(e) => println!("Failed to upgrade: {}", e),
+            }
+        }
+    }
+
+    let mut problems = 0;
+    let primitives_library = match load_primitives_definitions(&assets_path) {
+        Ok((primitives_library, diagnostics)) => {
+            for e in &diagnostics {
+                println!("{}", e);
+            }
+            problems += diagnostics.len();
+            println!("Loaded {} primitives", primitives_library.len());
+            primitives_library
+        },
+        Err(e) => {
+            println!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    match load_hidden_primitives_definitions(&assets_path) {
+        Ok((hidden_library, diagnostics)) => {
+            for e in &diagnostics {
+                println!("{}", e);
+            }
+            problems += diagnostics.len();
+            println!("Loaded {} hidden primitives", hidden_library.len());
+        },
+        Err(e) => {
+            println!("{}", e);
+            problems += 1;
+        }
+    }
+    let entities_library = match load_entities_definitions(&assets_path) {
+        Ok((entities_library, diagnostics)) => {
+            for e in &diagnostics {
+                println!("{}", e);
+            }
+            problems += diagnostics.len();
+            println!("Loaded {} entities", entities_library.len());
+            entities_library
+        },
+        Err(e) => {
+            println!("{}", e);
+            problems += 1;
+            HashMap::new()
+        }
+    };
+
+    for level_file in &level_files {
+        match load_level_definition(level_file) {
+            Ok(level) => {
+                let mut issues = validate_level(&level, &primitives_library);
+                issues.append(&mut validate_entities(&level, &primitives_library, &entities_library));
+                for issue in &issues {
+                    println!("{}: {}", level_file, issue);
+                }
+                problems += issues.len();
+            },
+            Err(e) => {
+                println!("{}", e);
+                problems += 1;
+            }
+        }
+    }
+
+    if problems > 0 {
+        println!("{} problem(s) found", problems);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,118 @@
+use std::env;
+use std::process;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::prelude::*;
+
+extern crate goldberg;
+use goldberg::engine::*;
+use goldberg::types::*;
+use goldberg::validation::*;
+
+// Headless simulation - Run a level without a window and dump the final state as json
+// Usage: goldberg-sim <level.json> <duration (s)> <timestep (s)> [output.json]
+// Without an output file the json goes to stdout, warnings and errors always go to stderr
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let usage = || {
+        eprintln!("Usage: {} <level.json> <duration (s)> <timestep (s)> [output.json]", args[0]);
+        process::exit(1);
+    };
+    if args.len() < 4 {
+        usage();
+    }
+    let level_file = args[1].clone();
+    // A zero timestep would never finish, and a negative one would silently do nothing
+    let duration : f32 = match args[2].parse() {
+        Ok(x) if x >= 0.0 && f32::is_finite(x) => x,
+        _ => {
+            eprintln!("ERROR: Duration must be a number of seconds, 0 or more: {}", args[2]);
+            usage()
+        }
+    };
+    let timestep : f32 = match args[3].parse() {
+        Ok(x) if x > 0.0 => x,
+        _ => {
+            eprintln!("ERROR: Timestep must be a number of seconds, more than 0: {}", args[3]);
+            usage()
+        }
+    };
+
+    let assets_path = String::from("assets/");
+    let level_definition = match load_level_definition(&level_file) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("ERROR: Failed to load level: {}", e);
+            process::exit(1);
+        }
+    };
+    let (primitives_library, primitive_diagnostics) = match load_primitives_definitions(&assets_path) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("ERROR: Failed to load primitives: {}", e);
+            process::exit(1);
+        }
+    };
+    for e in primitive_diagnostics {
+        eprintln!("WARNING: Skipped primitive: {}", e);
+    }
+    let hidden_primitives_library = match load_hidden_primitives_definitions(&assets_path) {
+        Ok((x, diagnostics)) => {
+            for e in diagnostics {
+                eprintln!("WARNING: Skipped primitive: {}", e);
+            }
+            x
+        },
+        Err(e) => {
+            eprintln!("WARNING: Failed to load hidden primitives: {}", e);
+            HashMap::new()
+        }
+    };
+    let entities_library = match load_entities_definitions(&assets_path) {
+        Ok((x, diagnostics)) => {
+            for e in diagnostics {
+                eprintln!("WARNING: Skipped entity: {}", e);
+            }
+            x
+        },
+        Err(e) => {
+            eprintln!("WARNING: Failed to load entities: {}", e);
+            HashMap::new()
+        }
+    };
+
+    for issue in validate_level(&level_definition, &primitives_library).iter().chain(&validate_entities(&level_definition, &primitives_library, &entities_library)) {
+        eprintln!("WARNING: {}", issue);
+    }
+
+    let mut sim = Simulation::new(primitives_library);
+    sim.hidden_primitives_library = hidden_primitives_library;
+    sim.entities_library = entities_library;
+    sim.timestep_override = Some(timestep);
+    for e in sim.load_level(&level_definition) {
+        eprintln!("WARNING: Failed to add to level: {}", e);
+    }
+
+    let steps = (duration / timestep).round() as u64;
+    for _ in 0..steps {
+        sim.step();
+    }
+
+    let result = SimulationResult {
+        level : level_definition.name.clone(),
+        time : sim.time(),
+        timestep : sim.timestep,
+        bodies : sim.body_states(),
+    };
+    let result_str = serde_json::to_string_pretty(&result).unwrap();
+
+    if args.len() > 4 {
+        let result_file = File::create(&args[4]).and_then(|mut file| file.write_all(result_str.as_bytes()));
+        if let Err(e) = result_file {
+            eprintln!("ERROR: Failed to write {}: {}", args[4], e);
+            process::exit(1);
+        }
+    } else {
+        println!("{}", result_str);
+    }
+}
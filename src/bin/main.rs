@@ -1,14 +1,6 @@
 extern crate kiss3d;
 extern crate nalgebra as na;
-use na::{Point3, Vector3,Isometry3};
-
-use ncollide3d::shape::{Cuboid, ShapeHandle};
-use nphysics3d::force_generator::DefaultForceGeneratorSet;
-use nphysics3d::joint::DefaultJointConstraintSet;
-use nphysics3d::object::{
-    BodyPartHandle, ColliderDesc, DefaultBodySet, DefaultColliderSet, Ground,
-};
-use nphysics3d::world::{DefaultGeometricalWorld, DefaultMechanicalWorld};
+use na::{Point3, Vector3};
 
 use kiss3d::light::Light;
 use kiss3d::window::{Window,CanvasSetup,NumSamples};
@@ -25,11 +17,35 @@ use goldberg::interactions::*;
 use goldberg::engine::*;
 use goldberg::types::*;
 
+fn interaction_name( mode : SimulationMode ) -> &'static str {
+    match mode {
+        SimulationMode::Edit => "EditorMode",
+        SimulationMode::Play => "PlayMode",
+        SimulationMode::Replay => "Replay",
+    }
+}
+
 fn main() {
     let assets_path = String::from("assets/");
     let mut level_file = assets_path.clone() + "/levels/default.json";
 
-    let args: Vec<String> = env::args().collect();
+    // Usage: goldberg [level name] [--record <trajectory.json>] [--replay <trajectory.json>] [--timestep <s>] [--max-substeps <n>]
+    let mut args: Vec<String> = Vec::new();
+    let mut record_file : Option<String> = None;
+    let mut replay_file : Option<String> = None;
+    let mut timestep_arg : Option<String> = None;
+    let mut max_substeps_arg : Option<String> = None;
+    let mut arg_it = env::args();
+    while let Some(arg) = arg_it.next() {
+        match arg.as_str() {
+            "--record" => record_file = arg_it.next(),
+            "--replay" => replay_file = arg_it.next(),
+            "--timestep" => timestep_arg = arg_it.next(),
+            "--max-substeps" => max_substeps_arg = arg_it.next(),
+            _ => args.push(arg),
+        }
+    }
+
     // TODO: This level loading is a mess, sort it out
     let mut level_definition : Option<LevelDefinition> = None;
 
@@ -38,27 +54,99 @@ fn main() {
         let passed_level_file = assets_path.clone() + "/levels/" + passed_level_name + ".json";
 
         level_file = passed_level_file;
-        if let Some(ldef) = load_level_definition(&level_file) {
-            level_definition = Some(ldef);
-        } else {
-            println!("WARNING: Failed to load level: {}", args[1]);
-            level_definition = load_level_empty(passed_level_name);
+        match load_level_definition(&level_file) {
+            Ok(ldef) => level_definition = Some(ldef),
+            Err(e) => {
+                println!("WARNING: Failed to load level: {}", e);
+                level_definition = load_level_empty(passed_level_name);
+            }
         }
     }
 
-    if !level_definition.is_some() {
+    if level_definition.is_none() {
         let passed_level_name = "default";
         let passed_level_file = assets_path.clone() + "/levels/" + passed_level_name + ".json";
 
         level_file = passed_level_file;
-        if let Some(ldef) = load_level_definition(&level_file) {
-            level_definition = Some(ldef);
-        } else {
-            println!("WARNING: Failed to load level: {}", args[1]);
-            level_definition = load_level_empty(passed_level_name);
+        match load_level_definition(&level_file) {
+            Ok(ldef) => level_definition = Some(ldef),
+            Err(e) => {
+                println!("WARNING: Failed to load level: {}", e);
+                level_definition = load_level_empty(passed_level_name);
+            }
+        }
+    }
+
+    // Override the level's step settings for this run, they aren't saved with the level
+    let timestep_override = match timestep_arg {
+        None => None,
+        Some(arg) => match arg.parse::<f32>() {
+            Ok(x) if x > 0.0 => Some(x),
+            _ => {
+                println!("ERROR: Timestep must be a number of seconds, more than 0: {}", arg);
+                return;
+            }
+        },
+    };
+    let max_substeps_override = match max_substeps_arg {
+        None => None,
+        Some(arg) => match arg.parse::<u32>() {
+            Ok(x) if x > 0 => Some(x),
+            _ => {
+                println!("ERROR: Max substeps must be a whole number, at least 1: {}", arg);
+                return;
+            }
+        },
+    };
+
+    let mut replay_trajectory = None;
+    if let Some(file) = &replay_file {
+        match load_trajectory(file) {
+            Ok(x) => replay_trajectory = Some(x),
+            Err(e) => {
+                println!("ERROR: Failed to load trajectory: {}", e);
+                return;
+            }
         }
     }
 
+    let (primitives_library, mut primitive_diagnostics) = match load_primitives_definitions(&assets_path) {
+        Ok(x) => x,
+        Err(e) => {
+            println!("ERROR: Failed to load primitives: {}", e);
+            return;
+        }
+    };
+    if primitives_library.is_empty() {
+        println!("ERROR: No primitives loaded");
+        return;
+    }
+
+    // Levels without entities work fine without these, so carry on if they're missing
+    let hidden_primitives_library = match load_hidden_primitives_definitions(&assets_path) {
+        Ok((x, mut diagnostics)) => {
+            primitive_diagnostics.append(&mut diagnostics);
+            x
+        },
+        Err(e) => {
+            println!("WARNING: Failed to load hidden primitives: {}", e);
+            HashMap::new()
+        }
+    };
+    let entities_library = match load_entities_definitions(&assets_path) {
+        Ok((x, mut diagnostics)) => {
+            primitive_diagnostics.append(&mut diagnostics);
+            x
+        },
+        Err(e) => {
+            println!("WARNING: Failed to load entities: {}", e);
+            HashMap::new()
+        }
+    };
+    for e in &primitive_diagnostics {
+        println!("WARNING: Skipped definition: {}", e);
+    }
+
     // Init graphics
     let window = Window::new_with_setup("Goldberg: Geefr's Physics Playground", 1280, 1024, CanvasSetup {
         vsync : false,
@@ -66,12 +154,11 @@ fn main() {
     });
 
     // Init physics
-    let mechanical_world = DefaultMechanicalWorld::new(Vector3::new(0.0, -9.81, 0.0));
-    let geometrical_world = DefaultGeometricalWorld::<f32>::new();
-    let bodies = DefaultBodySet::<f32>::new();
-    let colliders = DefaultColliderSet::new();
-    let joint_constrants = DefaultJointConstraintSet::<f32>::new();
-    let force_generators = DefaultForceGeneratorSet::<f32>::new();
+    let mut sim = Simulation::new(primitives_library);
+    sim.hidden_primitives_library = hidden_primitives_library;
+    sim.entities_library = entities_library;
+    sim.timestep_override = timestep_override;
+    sim.max_substeps_override = max_substeps_override;
 
     // TODO: We would use this, as needed for wasm compatibility,
     // but unfortunately we can't then render with anything except
@@ -81,44 +168,33 @@ fn main() {
     // - We can only get the Trait, not the camera itself
     // - We can't replace the camera on the window at all, there's no method to do it whatsoever
     // window.render_loop(state);
-    let mut camera = ArcBall::new(Point3::new(-50.0, 50.0, -50.0), Point3::new(0.0, 1.5, 0.0));
+    let world = &level_definition.as_ref().unwrap().world;
+    let mut camera = ArcBall::new(Point3::from(Vector3::from(world.camera_eye)), Point3::from(Vector3::from(world.camera_target)));
     camera.rebind_rotate_button(Some(MouseButton::Button2));
     camera.rebind_drag_button(Some(MouseButton::Button3));
     let planar_camera = FixedView::new();
 
     let mut state = AppState {
         window,
-        mechanical_world,
-        geometrical_world,
-        bodies,
-        colliders,
-        joint_constrants,
-        force_generators,
+        sim,
         physics_entities : Vec::new(),
         simulation_start_time : Instant::now(),
         simulation_last_update_ms : 0.0,
         assets_path : assets_path.clone(),
-        primitives_library : load_primitives_definitions(&assets_path).unwrap(),
         camera,
         planar_camera,
         level_definition : level_definition.unwrap(),
         level_file,
         render_debug_extents : false,
+        recording : None,
+        mode : SimulationMode::Edit,
+        diagnostics : primitive_diagnostics.iter().map(|e| e.to_string()).collect(),
+        level_issues : Vec::new(),
     };
 
     // Setup the scene based on the level definition
-    let ground_thickness = 0.1;
-    let ground_collision_cuboid = Cuboid::new(
-        Vector3::new(state.level_definition.ground_dimensions[0] / 2.0, ground_thickness / 2.0, state.level_definition.ground_dimensions[1] / 2.0)
-    );
-    let ground_shape = ShapeHandle::new(ground_collision_cuboid);
-    let ground_handle = state.bodies.insert(Ground::new());
-    let ground_collider = ColliderDesc::new(ground_shape)
-        .translation(Vector3::y() * - ground_thickness / 2.0)
-        .build(BodyPartHandle(ground_handle, 0));
-    state.colliders.insert(ground_collider);
-
-    let mut ground_geometry = state.window.add_cube(state.level_definition.ground_dimensions[0], ground_thickness, state.level_definition.ground_dimensions[1]);
+    let ground_collision_cuboid = ground_collision_cuboid(&state.level_definition);
+    let mut ground_geometry = state.window.add_cube(state.level_definition.ground_dimensions[0], GROUND_THICKNESS, state.level_definition.ground_dimensions[1]);
     // ground_geometry.append_translation(&Translation3::new(0.0, - ground_thickness / 2.0, 0.0));
     ground_geometry.set_color(
         state.level_definition.ground_colour[0],
@@ -139,17 +215,32 @@ fn main() {
     );
     
     state.add_primitives_from_level_definition();
+    for issue in &state.level_issues {
+        println!("WARNING: {}", issue);
+    }
 
     // Interactions
     let mut interactions : HashMap<String, Box<dyn Interaction>> = HashMap::new();
     interactions.insert(String::from("EditorMode"), Box::new(
-        EditorModeInteraction::new(ground_collision_cuboid, state.primitives_library.iter().next().unwrap().0)
+        EditorModeInteraction::new(ground_collision_cuboid, state.sim.primitives_library.iter().next().unwrap().0)
     ));
+    interactions.insert(String::from("PlayMode"), Box::new(PlayModeInteraction::new()));
 
-    let interaction = interactions.get_mut("EditorMode").unwrap();
+    if let Some(trajectory) = replay_trajectory {
+        if trajectory.level != state.level_definition.name {
+            println!("WARNING: Trajectory was recorded in level: {}", trajectory.level);
+        }
+        interactions.insert(String::from("Replay"), Box::new(ReplayInteraction::new(trajectory)));
+        state.mode = SimulationMode::Replay;
+    }
+    if record_file.is_some() {
+        state.recording = Some(Trajectory::new(&state.level_definition.name));
+    }
 
     state.simulation_start_time = Instant::now();
     while !state.window.should_close() {
+        let interaction = interactions.get_mut(interaction_name(state.mode)).unwrap();
+
         for event in state.window.events().iter() {
             match event.value {
                 WindowEvent::Key(k, Action::Press, modif) => {
@@ -175,37 +266,40 @@ fn main() {
         let simulation_delta = simulation_elapsed_ms - state.simulation_last_update_ms;
         state.simulation_last_update_ms = simulation_elapsed_ms;
 
-        state.mechanical_world.set_timestep( simulation_delta / 1000.0 );
-        state.mechanical_world.step(
-            &mut state.geometrical_world,
-            &mut state.bodies,
-            &mut state.colliders,
-            &mut state.joint_constrants,
-            &mut state.force_generators,
-        );
-
-        for ent in &mut state.physics_entities {
-            if let Some(co) = &state.colliders.get(ent.collider) {
-                let pos : Isometry3<f32> = na::convert_unchecked(*co.position());
-                // let collider_translation = Translation3::new(
-                //     - ent.collider_origin.x,
-                //     - ent.collider_origin.y,
-                //     - ent.collider_origin.z,
-                // );
-                // pos.append_translation_mut(&collider_translation);
-                ent.node.set_local_transformation(pos);
+        // Physics only runs in play mode, when replaying the interaction positions everything
+        if state.mode == SimulationMode::Play {
+            if state.sim.advance( simulation_delta / 1000.0 ) > 0 {
+                if let Some(trajectory) = &mut state.recording {
+                    trajectory.record_frame(&state.sim);
+                }
             }
+            // Entities may have spawned bodies
+            state.add_physics_entities();
+        }
+        if state.mode != SimulationMode::Replay {
+            state.sync_physics_entities();
+            let springs = state.sim.spring_endpoints();
+            state.draw_springs(&springs);
         }
 
         // state.window.set_light(Light::Absolute(Point3::new(
         //     state.level_definition.ground_dimensions[0],
         //     50.0,
         //     state.level_definition.ground_dimensions[1])));
-        state.window.set_light(Light::Absolute(Point3::new(0.0, 50.0, 0.0)));
+        // TODO: kiss3d doesn't support coloured lights, so world.light_colour isn't used yet, levels which set it get a level issue
+        state.window.set_light(Light::Absolute(Point3::from(Vector3::from(state.level_definition.world.light_position))));
 
         // Let the interaction render what it needs (cursors etc)
+        // The mode may have changed while handling events
+        let interaction = interactions.get_mut(interaction_name(state.mode)).unwrap();
         interaction.render(&mut state);
 
         state.window.render_with_camera(&mut state.camera); 
     }
+
+    if let (Some(trajectory), Some(file)) = (&state.recording, &record_file) {
+        if let Err(e) = save_trajectory(trajectory, file) {
+            println!("ERROR: Failed to save trajectory: {}", e);
+        }
+    }
 }
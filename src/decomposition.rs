@@ -0,0 +1,407 @@
+extern crate nalgebra as na;
+use na::{Point3, Vector3};
+
+use std::collections::HashSet;
+
+use ncollide3d::procedural;
+use ncollide3d::transformation;
+
+use crate::obj::ObjMesh;
+use crate::types::*;
+
+// Approximate convex decomposition, so concave meshes (bowls, cups, channels) can collide as a set of hulls
+// The mesh is voxelised, then the voxels are split along axis aligned planes until each piece
+// fills most of its own convex hull. Same idea as VHACD, just a lot less clever about it.
+// The mesh must be closed, otherwise the inside can't be worked out
+
+pub struct DecompositionSettings {
+    pub resolution : usize, // Voxels along the mesh's longest axis
+    pub max_hulls : usize,  // Most pieces to split into, separate parts of the mesh are merged if there's more
+    pub min_fill : f32,     // A piece is convex enough once its voxels fill this fraction of its hull
+}
+
+impl Default for DecompositionSettings {
+    fn default() -> Self {
+        DecompositionSettings {
+            resolution : 32,
+            max_hulls : 16,
+            min_fill : 0.85,
+        }
+    }
+}
+
+type Voxel = [usize; 3];
+
+struct VoxelGrid {
+    origin : Point3<f32>,
+    size : f32,
+    dims : [usize; 3],
+}
+
+impl VoxelGrid {
+    fn corner( &self, x : usize, y : usize, z : usize ) -> Point3<f32> {
+        self.origin + Vector3::new(x as f32, y as f32, z as f32) * self.size
+    }
+
+    // Nearest grid corner to a point
+    fn snap( &self, p : &Point3<f32> ) -> Point3<f32> {
+        self.origin + ((p - self.origin) / self.size).map(f32::round) * self.size
+    }
+}
+
+// A group of connected voxels and the hull around them
+struct Piece {
+    voxels : Vec<Voxel>,
+    hull_points : Vec<Point3<f32>>,
+    hull_volume : f32,
+    fill : f32,
+}
+
+// Split the mesh into convex hull colliders, these fit the mesh as exported like the other generated colliders
+// Returns nothing if the mesh has no volume
+pub fn decompose( mesh : &ObjMesh, settings : &DecompositionSettings ) -> Vec<ColliderDefinition> {
+    let (grid, voxels) = match voxelise(mesh, settings.resolution) {
+        Some(x) => x,
+        None => return Vec::new(),
+    };
+
+    let max_hulls = settings.max_hulls.max(1);
+    let mut pieces : Vec<Piece> = connected_pieces(voxels).into_iter()
+        .filter_map(|p| make_piece(&grid, p))
+        .collect();
+    // A mesh made of lots of separate parts can start off over budget
+    while pieces.len() > max_hulls {
+        merge_smallest(&grid, &mut pieces);
+    }
+
+    // Always split the least convex piece, so the hull budget goes where it's needed
+    while pieces.len() < max_hulls {
+        let worst = pieces.iter().enumerate()
+            .filter(|(_, p)| p.fill < settings.min_fill && p.voxels.len() > 1)
+            .min_by(|(_, a), (_, b)| a.fill.partial_cmp(&b.fill).unwrap())
+            .map(|(i, _)| i);
+        let worst = match worst {
+            Some(i) => i,
+            None => break,
+        };
+        match split_piece(&grid, &pieces[worst]) {
+            // A split can leave several pieces, only take it if they all fit
+            Some(parts) if pieces.len() + parts.len() - 1 <= max_hulls => {
+                pieces.swap_remove(worst);
+                pieces.extend(parts);
+            },
+            _ => break,
+        }
+    }
+
+    pieces.iter().map(|p| ColliderDefinition {
+        collider_type : ColliderType::ConvexHull,
+        origin : [0.0; 3],
+        rotation : [0.0; 3],
+        dimensions : [0.0; 3],
+        points : p.hull_points.iter().map(|v| [v.x, v.y, v.z]).collect(),
+        triangles : Vec::new(),
+    }).collect()
+}
+
+// Fill every voxel whose center is inside the mesh, by casting rays along x and pairing up the crossings
+fn voxelise( mesh : &ObjMesh, resolution : usize ) -> Option<(VoxelGrid, HashSet<Voxel>)> {
+    let (min, max) = mesh.bounds()?;
+    let extent = max - min;
+    let size = extent.max() / resolution.max(1) as f32;
+    if size <= 0.0 {
+        return None;
+    }
+    let grid = VoxelGrid {
+        origin : min,
+        size,
+        dims : [
+            (extent.x / size).ceil().max(1.0) as usize,
+            (extent.y / size).ceil().max(1.0) as usize,
+            (extent.z / size).ceil().max(1.0) as usize,
+        ],
+    };
+
+    let mut voxels = HashSet::new();
+    for y in 0..grid.dims[1] {
+        for z in 0..grid.dims[2] {
+            // Nudged off the voxel center so the ray doesn't run exactly along an edge of a grid aligned mesh
+            let ray_y = min.y + (y as f32 + 0.5013) * size;
+            let ray_z = min.z + (z as f32 + 0.4987) * size;
+            let mut hits = ray_hits_x(mesh, ray_y, ray_z);
+            hits.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            for span in hits.chunks_exact(2) {
+                for x in 0..grid.dims[0] {
+                    let center_x = min.x + (x as f32 + 0.5) * size;
+                    if center_x >= span[0] && center_x <= span[1] {
+                        voxels.insert([x, y, z]);
+                    }
+                }
+            }
+        }
+    }
+    if voxels.is_empty() {
+        return None;
+    }
+    Some((grid, voxels))
+}
+
+// x coordinates where a line parallel to the x axis crosses the mesh
+fn ray_hits_x( mesh : &ObjMesh, y : f32, z : f32 ) -> Vec<f32> {
+    let mut hits = Vec::new();
+    for t in &mesh.triangles {
+        let a = mesh.vertices[t[0]];
+        let b = mesh.vertices[t[1]];
+        let c = mesh.vertices[t[2]];
+        // Barycentric coordinates of (y, z) within the triangle projected onto the yz plane
+        let d = (b.y - a.y) * (c.z - a.z) - (c.y - a.y) * (b.z - a.z);
+        if d.abs() < f32::EPSILON {
+            continue;
+        }
+        let u = ((y - a.y) * (c.z - a.z) - (c.y - a.y) * (z - a.z)) / d;
+        let v = ((b.y - a.y) * (z - a.z) - (y - a.y) * (b.z - a.z)) / d;
+        if u >= 0.0 && v >= 0.0 && u + v <= 1.0 {
+            hits.push(a.x + u * (b.x - a.x) + v * (c.x - a.x));
+        }
+    }
+    hits
+}
+
+// Group voxels that share a face
+fn connected_pieces( mut remaining : HashSet<Voxel> ) -> Vec<Vec<Voxel>> {
+    let mut pieces = Vec::new();
+    while let Some(start) = remaining.iter().next().cloned() {
+        remaining.remove(&start);
+        let mut piece = Vec::new();
+        let mut open = vec![start];
+        while let Some(v) = open.pop() {
+            piece.push(v);
+            for axis in 0..3 {
+                for &up in &[false, true] {
+                    let mut n = v;
+                    if up {
+                        n[axis] += 1;
+                    } else if n[axis] > 0 {
+                        n[axis] -= 1;
+                    } else {
+                        continue;
+                    }
+                    if remaining.remove(&n) {
+                        open.push(n);
+                    }
+                }
+            }
+        }
+        pieces.push(piece);
+    }
+    pieces
+}
+
+fn make_piece( grid : &VoxelGrid, voxels : Vec<Voxel> ) -> Option<Piece> {
+    // Only voxels on the surface of the piece can contribute to its hull
+    let set : HashSet<Voxel> = voxels.iter().cloned().collect();
+    let mut corners = HashSet::new();
+    for v in &voxels {
+        let inside = (0..3).all(|axis| {
+            let mut lower = *v;
+            let mut upper = *v;
+            upper[axis] += 1;
+            lower[axis] = lower[axis].wrapping_sub(1);
+            set.contains(&lower) && set.contains(&upper)
+        });
+        if inside {
+            continue;
+        }
+        for dx in 0..2 {
+            for dy in 0..2 {
+                for dz in 0..2 {
+                    corners.insert([v[0] + dx, v[1] + dy, v[2] + dz]);
+                }
+            }
+        }
+    }
+    // Grid corners are massively coplanar, which gives convex_hull overlapping faces, so nudge each one slightly
+    let corners : Vec<Point3<f32>> = corners.iter().map(|c| grid.corner(c[0], c[1], c[2]) + jitter(c) * grid.size).collect();
+
+    let mut hull = transformation::convex_hull(&corners);
+    // The hull's points come back through a change of basis and can drift, every one of them is a corner though
+    for p in &mut hull.coords {
+        *p = grid.snap(p);
+    }
+    let hull_volume = mesh_volume(&hull);
+    if hull_volume <= 0.0 {
+        return None;
+    }
+    let voxel_volume = voxels.len() as f32 * grid.size.powi(3);
+    Some(Piece {
+        fill : (voxel_volume / hull_volume).min(1.0),
+        voxels,
+        hull_points : hull.coords,
+        hull_volume,
+    })
+}
+
+// Tiny offset for a grid corner, hashed from the corner so it's the same in every piece
+fn jitter( corner : &[usize; 3] ) -> Vector3<f32> {
+    let hash = |axis : usize| {
+        let h = corner[0].wrapping_mul(73_856_093) ^ corner[1].wrapping_mul(19_349_663)
+            ^ corner[2].wrapping_mul(83_492_791) ^ axis.wrapping_mul(2_654_435_761);
+        (h % 1000) as f32 / 1000.0 - 0.5
+    };
+    Vector3::new(hash(0), hash(1), hash(2)) * 1.0e-3
+}
+
+// Merge the piece with the fewest voxels into whichever other piece adds the least hull volume
+fn merge_smallest( grid : &VoxelGrid, pieces : &mut Vec<Piece> ) {
+    let smallest = match pieces.iter().enumerate().min_by_key(|(_, p)| p.voxels.len()) {
+        Some((i, _)) => i,
+        None => return,
+    };
+    let piece = pieces.swap_remove(smallest);
+    let mut best : Option<(usize, Piece)> = None;
+    for (i, other) in pieces.iter().enumerate() {
+        let voxels = other.voxels.iter().chain(&piece.voxels).cloned().collect();
+        if let Some(merged) = make_piece(grid, voxels) {
+            let cost = merged.hull_volume - other.hull_volume;
+            if best.as_ref().is_none_or(|(j, b)| cost < b.hull_volume - pieces[*j].hull_volume) {
+                best = Some((i, merged));
+            }
+        }
+    }
+    if let Some((i, merged)) = best {
+        pieces[i] = merged;
+    }
+}
+
+// Volume of a closed convex mesh, as tetrahedra from its centroid
+fn mesh_volume( mesh : &procedural::TriMesh<f32> ) -> f32 {
+    if mesh.coords.is_empty() {
+        return 0.0;
+    }
+    let centroid = Point3::from(mesh.coords.iter().fold(Vector3::zeros(), |sum, p| sum + p.coords) / mesh.coords.len() as f32);
+    mesh.flat_indices().chunks_exact(3).map(|t| {
+        let a = mesh.coords[t[0] as usize] - centroid;
+        let b = mesh.coords[t[1] as usize] - centroid;
+        let c = mesh.coords[t[2] as usize] - centroid;
+        a.dot(&b.cross(&c)).abs() / 6.0
+    }).sum()
+}
+
+// Planes tried along each axis when splitting a piece
+const SPLIT_CANDIDATES : usize = 7;
+
+// Split along whichever plane leaves the least hull volume, so the cut goes through the concave part
+// Each half may fall apart into several connected pieces
+fn split_piece( grid : &VoxelGrid, piece : &Piece ) -> Option<Vec<Piece>> {
+    let mut best : Option<(f32, Vec<Piece>)> = None;
+    for axis in 0..3 {
+        let lo = piece.voxels.iter().map(|v| v[axis]).min()?;
+        let hi = piece.voxels.iter().map(|v| v[axis]).max()?;
+        if hi == lo {
+            continue;
+        }
+        let steps = (hi - lo).min(SPLIT_CANDIDATES);
+        for step in 1..=steps {
+            let plane = lo + ((hi - lo) * step + steps / 2) / (steps + 1) + 1;
+            let (below, above) : (HashSet<Voxel>, HashSet<Voxel>) = piece.voxels.iter().partition(|v| v[axis] < plane);
+            if below.is_empty() || above.is_empty() {
+                continue;
+            }
+            let parts : Vec<Piece> = connected_pieces(below).into_iter()
+                .chain(connected_pieces(above))
+                .filter_map(|p| make_piece(grid, p))
+                .collect();
+            let cost : f32 = parts.iter().map(|p| p.hull_volume).sum();
+            if best.as_ref().is_none_or(|(best_cost, _)| cost < *best_cost) {
+                best = Some((cost, parts));
+            }
+        }
+    }
+    best.map(|(_, parts)| parts).filter(|parts| !parts.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Closed box between two corners, added onto mesh
+    fn add_box( mesh : &mut ObjMesh, min : [f32; 3], max : [f32; 3] ) {
+        let first = mesh.vertices.len();
+        for i in 0..8 {
+            mesh.vertices.push(Point3::new(
+                if i & 1 == 0 { min[0] } else { max[0] },
+                if i & 2 == 0 { min[1] } else { max[1] },
+                if i & 4 == 0 { min[2] } else { max[2] },
+            ));
+        }
+        for face in &[[0, 2, 3, 1], [4, 5, 7, 6], [0, 1, 5, 4], [2, 6, 7, 3], [0, 4, 6, 2], [1, 3, 7, 5]] {
+            mesh.triangles.push([first + face[0], first + face[1], first + face[2]]);
+            mesh.triangles.push([first + face[0], first + face[2], first + face[3]]);
+        }
+    }
+
+    fn boxes( corners : &[([f32; 3], [f32; 3])] ) -> ObjMesh {
+        let mut mesh = ObjMesh { vertices : Vec::new(), triangles : Vec::new() };
+        for (min, max) in corners {
+            add_box(&mut mesh, *min, *max);
+        }
+        mesh
+    }
+
+    fn settings( max_hulls : usize ) -> DecompositionSettings {
+        DecompositionSettings { resolution : 16, max_hulls, ..DecompositionSettings::default() }
+    }
+
+    // L shape with long arms, two boxes sharing part of a face, fills ~60% of its hull
+    fn l_shape() -> ObjMesh {
+        boxes(&[([0.0, 0.0, 0.0], [4.0, 1.0, 1.0]), ([0.0, 1.0, 0.0], [1.0, 4.0, 1.0])])
+    }
+
+    #[test]
+    fn convex_mesh_is_one_hull() {
+        let hulls = decompose(&boxes(&[([0.0, 0.0, 0.0], [1.0, 2.0, 3.0])]), &settings(16));
+        assert_eq!(hulls.len(), 1);
+        assert!(matches!(hulls[0].collider_type, ColliderType::ConvexHull));
+        // Hulls are built from voxel corners, so they stay within a voxel of the box
+        for p in &hulls[0].points {
+            assert!(p.iter().zip(&[1.0, 2.0, 3.0]).all(|(x, max)| *x >= -1.0e-4 && *x <= max + 0.2), "{:?}", p);
+        }
+    }
+
+    #[test]
+    fn solid_pieces_fill_their_hull() {
+        let (grid, voxels) = voxelise(&boxes(&[([0.0, 0.0, 0.0], [1.0, 2.0, 3.0])]), 16).unwrap();
+        for _ in 0..20 {
+            let piece = make_piece(&grid, voxels.iter().cloned().collect()).unwrap();
+            assert!(piece.fill > 0.99, "fill {}", piece.fill);
+        }
+    }
+
+    #[test]
+    fn concave_mesh_is_split() {
+        let hulls = decompose(&l_shape(), &settings(16));
+        assert!(hulls.len() > 1 && hulls.len() <= 16);
+    }
+
+    #[test]
+    fn max_hulls_is_a_cap() {
+        assert_eq!(decompose(&l_shape(), &settings(1)).len(), 1);
+        assert_eq!(decompose(&l_shape(), &settings(0)).len(), 1);
+
+        // Separate parts are merged when there's more of them than hulls
+        let apart = boxes(&[
+            ([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]),
+            ([3.0, 0.0, 0.0], [4.0, 1.0, 1.0]),
+            ([6.0, 0.0, 0.0], [7.0, 1.0, 1.0]),
+        ]);
+        assert_eq!(decompose(&apart, &settings(16)).len(), 3);
+        assert_eq!(decompose(&apart, &settings(2)).len(), 2);
+        assert_eq!(decompose(&apart, &settings(1)).len(), 1);
+    }
+
+    #[test]
+    fn flat_mesh_has_no_hulls() {
+        assert!(decompose(&boxes(&[([0.0, 0.0, 0.0], [1.0, 1.0, 0.0])]), &settings(16)).is_empty());
+        assert!(decompose(&boxes(&[]), &settings(16)).is_empty());
+    }
+}
@@ -1,15 +1,19 @@
 extern crate kiss3d;
 extern crate nalgebra as na;
-use na::{Point3, Vector3,Isometry3};
+use na::{Point3, Vector3, Isometry3, Translation3, Quaternion, UnitQuaternion, Unit};
 
-use ncollide3d::shape::{Cuboid, Ball, ShapeHandle, Compound};
+use ncollide3d::shape::{Cuboid, Ball, Capsule, ConvexHull, ShapeHandle, Compound, TriMesh};
 use nphysics3d::force_generator::DefaultForceGeneratorSet;
-use nphysics3d::joint::DefaultJointConstraintSet;
+use nphysics3d::joint::{
+    DefaultJointConstraintSet, DefaultJointConstraintHandle, RevoluteConstraint, BallConstraint, PrismaticConstraint, FixedConstraint,
+};
 use nphysics3d::object::{
-    BodyPartHandle, ColliderDesc, DefaultBodySet, DefaultColliderSet, DefaultColliderHandle, RigidBodyDesc, BodyStatus, 
+    Body, BodyPart, BodyPartHandle, ColliderDesc, DefaultBodySet, DefaultBodyHandle, DefaultColliderSet, DefaultColliderHandle, RigidBodyDesc, BodyStatus, Ground,
 };
 use nphysics3d::material::{MaterialHandle, BasicMaterial};
+use nphysics3d::math::{Velocity as Velocity3, Force as Force3, ForceType};
 use nphysics3d::world::{DefaultGeometricalWorld, DefaultMechanicalWorld};
+use ncollide3d::pipeline::object::CollisionGroups;
 
 use kiss3d::scene::SceneNode;
 use kiss3d::window::{Window};
@@ -23,10 +27,18 @@ use std::path::Path;
 use std::fs::{self, File};
 use std::io;
 use std::io::prelude::*;
-use std::io::BufReader;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
 
 
 use crate::types::*;
+use crate::error::GoldbergError;
+use crate::migration::*;
+use crate::obj::load_obj;
+use crate::validation::*;
+use crate::forces::*;
 
 // Global state
 pub struct PhysicsEntity {
@@ -34,202 +46,1631 @@ pub struct PhysicsEntity {
     // pub collider_origin : Vector3<f32>,
     pub node : SceneNode,
 }
-pub struct AppState {
-    pub window : Window,
-    pub assets_path : String,
+
+// A primitive which has been added to the simulation
+pub struct SimulationPrimitive {
+    pub name : String,
+    pub scale : Vector3<f32>,
+    pub colour : Option<[f32; 3]>,
+    pub body : DefaultBodyHandle,
+    pub collider : DefaultColliderHandle, // The first collider, all of a primitive's colliders share its pose
+    pub spawned : bool, // Spawned by an entity, rather than placed in the level
+}
+
+// An entity which has been added to the simulation, its body is also in the primitives list
+pub struct SimulationEntity {
+    pub name : String,
+    pub entity_type : EntityType, // Parameters, with any overrides from the level applied
+    pub body : DefaultBodyHandle,
+    pub active : bool,
+    pub ammo : u32, // Shots left
+    pub cooldown : f32, // Seconds until it can fire again
+    pub switched : Option<bool>, // State last applied by a switch, None until it's first applied
+}
+
+// A motor on one of the joints, nphysics constraints don't have motors so these are applied each step
+pub struct SimulationMotor {
+    pub joint : DefaultJointConstraintHandle,
+    pub body1 : DefaultBodyHandle,
+    pub body2 : DefaultBodyHandle,
+    pub axis : Unit<Vector3<f32>>, // In body1's frame
+    pub angular : bool, // Rotates about the axis if true, slides along it if false
+    pub target_velocity : f32,
+    pub max_force : f32,
+    pub enabled : bool,
+}
+
+// Angle limits on a revolute joint, nphysics constraints don't have them so these are applied each step
+// The angle is body1's rotation relative to body2 about the axis, 0 as the bodies were placed
+pub struct SimulationHingeLimit {
+    pub body1 : DefaultBodyHandle,
+    pub body2 : DefaultBodyHandle,
+    pub axis : Unit<Vector3<f32>>, // In body1's frame
+    pub reference1 : Vector3<f32>, // A direction at right angles to the axis, in body1's frame
+    pub reference2 : Vector3<f32>, // The same direction in body2's frame
+    pub min : f32, // Radians
+    pub max : f32,
+}
+
+// Collision group of cannons, which their projectiles ignore
+const CANNON_GROUP : usize = 1;
+
+// Fraction of a limit's overshoot which is corrected each step
+const LIMIT_CORRECTION : f32 = 0.2;
+
+// The physics side of the application, doesn't know anything about rendering
+// so can be stepped without a window (headless tools, CI, etc)
+pub struct Simulation {
     pub mechanical_world: DefaultMechanicalWorld<f32>,
     pub geometrical_world: DefaultGeometricalWorld::<f32>,
     pub bodies: DefaultBodySet::<f32>,
     pub colliders: DefaultColliderSet<f32>,
     pub joint_constrants: DefaultJointConstraintSet::<f32>,
     pub force_generators: DefaultForceGeneratorSet::<f32>,
+    pub primitives_library : HashMap<String, PrimitiveDefinition>,
+    // Primitives which entities spawn, these can't be placed directly
+    pub hidden_primitives_library : HashMap<String, PrimitiveDefinition>,
+    pub entities_library : HashMap<String, EntityDefinition>,
+    // Every primitive added so far, in the order they were added
+    // Includes the bodies of entities, and anything they've spawned
+    pub primitives : Vec<SimulationPrimitive>,
+    pub entities : Vec<SimulationEntity>,
+    // One per joint in the level, None if it couldn't be built
+    pub joints : Vec<Option<DefaultJointConstraintHandle>>,
+    pub motors : Vec<SimulationMotor>,
+    pub hinge_limits : Vec<SimulationHingeLimit>,
+    // The level's springs, the forces are applied by force generators
+    pub springs : Vec<SpringLink>,
+    // Sampled volume of each dynamic body, for buoyancy
+    pub body_volumes : BodyVolumes,
+    // Copy of mechanical_world.gravity for force generators, updated before each step
+    pub shared_gravity : SharedGravity,
+    // The world, for anything which is attached to it. Set when a level is loaded
+    pub ground : Option<DefaultBodyHandle>,
+    // Fixed size of each physics step, in seconds
+    pub timestep : f32,
+    // Maximum number of steps taken by a single call to advance
+    pub max_substeps : u32,
+    // Used instead of the level's timestep and max_substeps when set, e.g. from the command line
+    pub timestep_override : Option<f32>,
+    pub max_substeps_override : Option<u32>,
+    // Number of steps taken since the simulation was created
+    pub step_count : u64,
+    // Time passed to advance which hasn't been simulated yet
+    accumulator : f32,
+}
+impl Simulation {
+    // Remove everything from the world, the library and settings are kept
+    pub fn clear( &mut self ) {
+        let mut mechanical_world = DefaultMechanicalWorld::new(self.mechanical_world.gravity);
+        mechanical_world.set_timestep(self.timestep);
+        self.mechanical_world = mechanical_world;
+        self.geometrical_world = DefaultGeometricalWorld::<f32>::new();
+        self.bodies = DefaultBodySet::<f32>::new();
+        self.colliders = DefaultColliderSet::new();
+        self.joint_constrants = DefaultJointConstraintSet::<f32>::new();
+        self.force_generators = DefaultForceGeneratorSet::<f32>::new();
+        self.primitives.clear();
+        self.entities.clear();
+        self.joints.clear();
+        self.motors.clear();
+        self.hinge_limits.clear();
+        self.springs.clear();
+        if let Ok(mut volumes) = self.body_volumes.write() {
+            volumes.clear();
+        }
+        self.ground = None;
+        self.step_count = 0;
+        self.accumulator = 0.0;
+    }
+
+    pub fn new( primitives_library : HashMap<String, PrimitiveDefinition> ) -> Self {
+        let mut mechanical_world = DefaultMechanicalWorld::new(Vector3::new(0.0, -9.81, 0.0));
+        mechanical_world.set_timestep(DEFAULT_TIMESTEP);
+        Simulation {
+            mechanical_world,
+            geometrical_world : DefaultGeometricalWorld::<f32>::new(),
+            bodies : DefaultBodySet::<f32>::new(),
+            colliders : DefaultColliderSet::new(),
+            joint_constrants : DefaultJointConstraintSet::<f32>::new(),
+            force_generators : DefaultForceGeneratorSet::<f32>::new(),
+            primitives_library,
+            hidden_primitives_library : HashMap::new(),
+            entities_library : HashMap::new(),
+            primitives : Vec::new(),
+            entities : Vec::new(),
+            joints : Vec::new(),
+            motors : Vec::new(),
+            hinge_limits : Vec::new(),
+            springs : Vec::new(),
+            body_volumes : BodyVolumes::default(),
+            shared_gravity : SharedGravity::default(),
+            ground : None,
+            timestep : DEFAULT_TIMESTEP,
+            max_substeps : DEFAULT_MAX_SUBSTEPS,
+            timestep_override : None,
+            max_substeps_override : None,
+            step_count : 0,
+            accumulator : 0.0,
+        }
+    }
+
+    // Add the ground and every primitive and entity in the level
+    // Anything which fails to load is skipped, returns the errors for them
+    pub fn load_level( &mut self, level : &LevelDefinition ) -> Vec<GoldbergError> {
+        let mut errors = Vec::new();
+        self.mechanical_world.gravity = Vector3::from(level.world.gravity);
+        if let Some(solver) = &level.world.solver {
+            self.mechanical_world.integration_parameters.max_velocity_iterations = solver.max_velocity_iterations;
+            self.mechanical_world.integration_parameters.max_position_iterations = solver.max_position_iterations;
+        }
+        // A bad setting would stop the simulation, keep the previous one instead
+        match self.timestep_override {
+            Some(x) => self.timestep = x,
+            None if level.world.timestep > 0.0 => self.timestep = level.world.timestep,
+            None => errors.push(GoldbergError::Validation(format!("World timestep must be more than 0, using {}", self.timestep))),
+        }
+        match self.max_substeps_override {
+            Some(x) => self.max_substeps = x,
+            None if level.world.max_substeps > 0 => self.max_substeps = level.world.max_substeps,
+            None => errors.push(GoldbergError::Validation(format!("World max_substeps must be at least 1, using {}", self.max_substeps))),
+        }
+
+        let ground_shape = ShapeHandle::new(ground_collision_cuboid(level));
+        let ground_handle = self.bodies.insert(Ground::new());
+        let ground_collider = ColliderDesc::new(ground_shape)
+            .translation(Vector3::y() * - GROUND_THICKNESS / 2.0)
+            .build(BodyPartHandle(ground_handle, 0));
+        self.colliders.insert(ground_collider);
+        self.ground = Some(ground_handle);
+
+        // Body of each of the level's primitives, for joints to refer to
+        let mut placed = Vec::new();
+        for prim in &level.primitives {
+            match self.add_primitive(prim) {
+                Ok(()) => placed.push(self.primitives.last().map(|p| p.body)),
+                Err(e) => {
+                    errors.push(e);
+                    placed.push(None);
+                }
+            }
+        }
+        for ent in &level.entities {
+            if let Err(e) = self.add_entity(ent) {
+                errors.push(e);
+            }
+        }
+        for zone in &level.force_zones {
+            self.force_generators.insert(Box::new(ForceZone::new(zone)));
+        }
+        for fluid in &level.fluids {
+            self.force_generators.insert(Box::new(FluidVolume::new(fluid, self.shared_gravity.clone(), self.body_volumes.clone())));
+        }
+        for (i, spring) in level.springs.iter().enumerate() {
+            if let Err(e) = self.add_spring(spring, &placed) {
+                errors.push(GoldbergError::Validation(format!("Spring {}: {}", i, e)));
+            }
+        }
+        for (i, joint) in level.joints.iter().enumerate() {
+            match self.add_joint(joint, &placed) {
+                Ok(handle) => self.joints.push(Some(handle)),
+                Err(e) => {
+                    errors.push(GoldbergError::Validation(format!("Joint {}: {}", i, e)));
+                    self.joints.push(None);
+                }
+            }
+        }
+        // Switches can only be checked once the joints are in
+        for ent in &self.entities {
+            if let EntityType::MotorSwitch { joint } = &ent.entity_type {
+                if let Err(message) = check_motor_switch(level, *joint) {
+                    errors.push(GoldbergError::Validation(format!("{}: {}", ent.name, message)));
+                }
+            }
+        }
+        errors
+    }
+
+    // Body of one of the level's primitives, placed holds the body of each of them
+    // None is the ground, for anything attached to the world
+    fn placed_body( &self, index : Option<usize>, placed : &[Option<DefaultBodyHandle>] ) -> Result<DefaultBodyHandle, GoldbergError> {
+        match index {
+            Some(i) => placed.get(i).cloned().flatten()
+                .ok_or_else(|| GoldbergError::Validation(format!("Body {} wasn't added", i))),
+            None => self.ground.ok_or_else(|| GoldbergError::Validation(String::from("No level loaded"))),
+        }
+    }
+
+    // Current pose of a body, the ground doesn't move so its frame is the world's
+    fn body_pose( &self, handle : DefaultBodyHandle ) -> Isometry3<f32> {
+        self.bodies.rigid_body(handle).map_or_else(Isometry3::identity, |rb| *rb.position())
+    }
+
+    // Connect two bodies with a spring, driven by its own force generator
+    // Anchors are converted into each body's frame, so the bodies must still be where they were placed
+    pub fn add_spring( &mut self, def : &LevelSpringDefinition, placed : &[Option<DefaultBodyHandle>] ) -> Result<(), GoldbergError> {
+        let body1 = self.placed_body(Some(def.body1), placed)?;
+        let body2 = self.placed_body(def.body2, placed)?;
+        let anchor1 = Point3::from(Vector3::from(def.anchor1));
+        let anchor2 = Point3::from(Vector3::from(def.anchor2));
+        let link = SpringLink {
+            body1,
+            anchor1 : self.body_pose(body1).inverse_transform_point(&anchor1),
+            body2,
+            anchor2 : self.body_pose(body2).inverse_transform_point(&anchor2),
+            rest_length : def.rest_length.unwrap_or_else(|| na::distance(&anchor1, &anchor2)),
+            stiffness : def.stiffness,
+            damping : def.damping,
+            rope : def.rope,
+        };
+        self.force_generators.insert(Box::new(Spring { link }));
+        self.springs.push(link);
+        Ok(())
+    }
+
+    // World positions of both ends of each spring
+    pub fn spring_endpoints( &self ) -> Vec<(Point3<f32>, Point3<f32>)> {
+        self.spring_endpoints_at(|_| None)
+    }
+
+    // World positions of both ends of each spring, with bodies posed from elsewhere (e.g. a replay)
+    // pose_of is given an index into primitives, bodies it doesn't have a pose for stay where they are
+    pub fn spring_endpoints_at<F : Fn(usize) -> Option<Isometry3<f32>>>( &self, pose_of : F ) -> Vec<(Point3<f32>, Point3<f32>)> {
+        let pose = |body| self.primitives.iter().position(|p| p.body == body)
+            .and_then(&pose_of)
+            .unwrap_or_else(|| self.body_pose(body));
+        self.springs.iter()
+            .map(|link| (pose(link.body1) * link.anchor1, pose(link.body2) * link.anchor2))
+            .collect()
+    }
+
+    // Connect two bodies, placed holds the body of each of the level's primitives
+    // The anchor and axis are converted into each body's frame, so the bodies must still be where they were placed
+    pub fn add_joint( &mut self, def : &LevelJointDefinition, placed : &[Option<DefaultBodyHandle>] ) -> Result<DefaultJointConstraintHandle, GoldbergError> {
+        let body1 = self.placed_body(Some(def.body1), placed)?;
+        let body2 = self.placed_body(def.body2, placed)?;
+        let pose1 = self.body_pose(body1);
+        let pose2 = self.body_pose(body2);
+
+        let anchor = Point3::from(Vector3::from(def.anchor));
+        let anchor1 = pose1.inverse_transform_point(&anchor);
+        let anchor2 = pose2.inverse_transform_point(&anchor);
+        let part1 = BodyPartHandle(body1, 0);
+        let part2 = BodyPartHandle(body2, 0);
+
+        let mut motor = None;
+        let handle = match &def.joint_type {
+            JointType::Revolute { axis, limits, motor : motor_def } => {
+                let (axis1, axis2) = joint_axes(axis, &pose1, &pose2)?;
+                if let Some(limits) = limits {
+                    let world_axis = pose1.rotation * axis1.into_inner();
+                    let reference = perpendicular(&world_axis);
+                    self.hinge_limits.push(SimulationHingeLimit {
+                        body1,
+                        body2,
+                        axis : axis1,
+                        reference1 : pose1.inverse_transform_vector(&reference),
+                        reference2 : pose2.inverse_transform_vector(&reference),
+                        min : limits[0].to_radians(),
+                        max : limits[1].to_radians(),
+                    });
+                }
+                motor = motor_def.as_ref().map(|m| (m, axis1, true));
+                self.joint_constrants.insert(RevoluteConstraint::new(part1, part2, anchor1, axis1, anchor2, axis2))
+            },
+            JointType::Ball => {
+                self.joint_constrants.insert(BallConstraint::new(part1, part2, anchor1, anchor2))
+            },
+            JointType::Prismatic { axis, limits, motor : motor_def } => {
+                let (axis1, _) = joint_axes(axis, &pose1, &pose2)?;
+                let mut joint = PrismaticConstraint::new(part1, part2, anchor1, axis1, anchor2);
+                if let Some(limits) = limits {
+                    joint.enable_min_offset(limits[0]);
+                    joint.enable_max_offset(limits[1]);
+                }
+                motor = motor_def.as_ref().map(|m| (m, axis1, false));
+                self.joint_constrants.insert(joint)
+            },
+            JointType::Fixed => {
+                self.joint_constrants.insert(FixedConstraint::new(part1, part2, anchor1, pose1.rotation.inverse(), anchor2, pose2.rotation.inverse()))
+            },
+        };
+
+        if let Some((motor_def, axis, angular)) = motor {
+            self.motors.push(SimulationMotor {
+                joint : handle,
+                body1,
+                body2,
+                axis,
+                angular,
+                target_velocity : motor_def.target_velocity,
+                max_force : motor_def.max_force,
+                enabled : motor_def.enabled,
+            });
+        }
+        Ok(handle)
+    }
+
+    // Switch the motor on one of the level's joints on or off
+    // Returns false if the joint doesn't have a motor
+    pub fn set_motor_enabled( &mut self, joint : usize, enabled : bool ) -> bool {
+        let handle = match self.joints.get(joint) {
+            Some(Some(x)) => *x,
+            _ => return false,
+        };
+        match self.motors.iter_mut().find(|m| m.joint == handle) {
+            Some(motor) => {
+                motor.enabled = enabled;
+                true
+            },
+            None => false,
+        }
+    }
+
+    // Relative velocity of body1 to body2 about/along a world space axis, and how much the bodies resist changing it
+    // Static bodies and the ground don't move, so don't contribute
+    fn axis_response( &self, body1 : DefaultBodyHandle, body2 : DefaultBodyHandle, axis : &Vector3<f32>, angular : bool ) -> (f32, f32) {
+        let mut relative_velocity = 0.0;
+        let mut inverse_mass = 0.0;
+        for (handle, sign) in &[(body1, 1.0), (body2, -1.0)] {
+            if let Some(rb) = self.bodies.rigid_body(*handle) {
+                let vel = rb.velocity();
+                let inertia = rb.inertia();
+                if angular {
+                    relative_velocity += sign * vel.angular.dot(axis);
+                } else {
+                    relative_velocity += sign * vel.linear.dot(axis);
+                }
+                if rb.is_dynamic() {
+                    inverse_mass += if angular {
+                        inertia.angular.try_inverse().map_or(0.0, |inv| axis.dot(&(inv * axis)))
+                    } else if inertia.linear > 0.0 {
+                        1.0 / inertia.linear
+                    } else {
+                        0.0
+                    };
+                }
+            }
+        }
+        (relative_velocity, inverse_mass)
+    }
+
+    // Push body1 and body2 apart about/along a world space axis, equal and opposite
+    fn apply_axis_impulse( &mut self, body1 : DefaultBodyHandle, body2 : DefaultBodyHandle, axis : &Vector3<f32>, angular : bool, impulse : f32 ) {
+        for (handle, sign) in &[(body1, 1.0), (body2, -1.0)] {
+            if let Some(rb) = self.bodies.rigid_body_mut(*handle) {
+                let force = if angular {
+                    Force3::torque(axis * impulse * *sign)
+                } else {
+                    Force3::linear(axis * impulse * *sign)
+                };
+                rb.apply_force(0, &force, ForceType::Impulse, true);
+            }
+        }
+    }
+
+    // Push each motor's bodies towards its target velocity, called before each step
+    // The impulse is what it would take to reach the target in one step, limited by the motor's max force
+    fn apply_motors( &mut self ) {
+        for i in 0..self.motors.len() {
+            let motor = &self.motors[i];
+            if !motor.enabled {
+                continue;
+            }
+            let (body1, body2, angular) = (motor.body1, motor.body2, motor.angular);
+            let axis = match self.bodies.rigid_body(body1) {
+                Some(rb) => rb.position().rotation * motor.axis.into_inner(),
+                None => continue,
+            };
+            let (relative_velocity, inverse_mass) = self.axis_response(body1, body2, &axis, angular);
+            if inverse_mass <= 0.0 {
+                continue;
+            }
+            let max_impulse = motor.max_force * self.timestep;
+            let impulse = ((motor.target_velocity - relative_velocity) / inverse_mass).max(-max_impulse).min(max_impulse);
+            self.apply_axis_impulse(body1, body2, &axis, angular, impulse);
+        }
+    }
+
+    // Stop hinges turning past their limits, called before each step after the motors
+    // Past a limit the bodies are stopped from turning any further, and eased back within it
+    fn apply_hinge_limits( &mut self ) {
+        for i in 0..self.hinge_limits.len() {
+            let limit = &self.hinge_limits[i];
+            let (body1, body2) = (limit.body1, limit.body2);
+            let pose1 = self.body_pose(body1);
+            let pose2 = self.body_pose(body2);
+            let axis = pose1.rotation * limit.axis.into_inner();
+            let reference1 = pose1.rotation * limit.reference1;
+            let reference2 = pose2.rotation * limit.reference2;
+            let angle = axis.dot(&reference2.cross(&reference1)).atan2(reference2.dot(&reference1));
+
+            // Velocity needed to get back within the limit, only ever pushes away from it
+            let (target, past_min) = if angle < limit.min {
+                ((limit.min - angle) * LIMIT_CORRECTION / self.timestep, true)
+            } else if angle > limit.max {
+                ((limit.max - angle) * LIMIT_CORRECTION / self.timestep, false)
+            } else {
+                continue;
+            };
+            let (relative_velocity, inverse_mass) = self.axis_response(body1, body2, &axis, true);
+            if inverse_mass <= 0.0 {
+                continue;
+            }
+            let impulse = (target - relative_velocity) / inverse_mass;
+            let impulse = if past_min { impulse.max(0.0) } else { impulse.min(0.0) };
+            self.apply_axis_impulse(body1, body2, &axis, true, impulse);
+        }
+    }
+
+    pub fn add_primitive( &mut self, def : &LevelPrimitiveDefinition ) -> Result<(), GoldbergError> {
+        let prim = self.primitives_library.get(&def.name).ok_or_else(|| GoldbergError::UnknownPrimitive(def.name.clone()))?;
+        let sim_prim = build_primitive_body(&mut self.bodies, &mut self.colliders, &self.body_volumes, prim, def)?;
+        self.primitives.push(sim_prim);
+        Ok(())
+    }
+
+    pub fn add_entity( &mut self, def : &LevelEntityDefinition ) -> Result<(), GoldbergError> {
+        let ent = self.entities_library.get(&def.name).ok_or_else(|| GoldbergError::UnknownEntity(def.name.clone()))?;
+        let entity_type = entity_parameters(ent, def)?;
+        let placement = primitive_placement(&ent.primitive.name, def.position, def.rotation, def.is_static);
+        let sim_prim = build_primitive_body(&mut self.bodies, &mut self.colliders, &self.body_volumes, &ent.primitive, &placement)?;
+        let active = def.active_default.unwrap_or(ent.active_default);
+
+        let ammo = match &entity_type {
+            EntityType::Cannon { ammo, .. } => *ammo,
+            EntityType::MotorSwitch { .. } => 0,
+        };
+        if let EntityType::Cannon { .. } = entity_type {
+            self.set_collision_groups(sim_prim.body, CollisionGroups::new().with_membership(&[CANNON_GROUP]));
+        }
+        self.entities.push(SimulationEntity {
+            name : def.name.clone(),
+            entity_type,
+            body : sim_prim.body,
+            active,
+            ammo,
+            cooldown : 0.0,
+            switched : None,
+        });
+        self.primitives.push(sim_prim);
+        Ok(())
+    }
+
+    // Turn every entity on or off
+    pub fn set_entities_active( &mut self, active : bool ) {
+        for ent in &mut self.entities {
+            ent.active = active;
+        }
+    }
+
+    // Definition of a body in the simulation, whether it was placed, is part of an entity or was spawned by one
+    pub fn primitive_definition( &self, name : &str ) -> Option<&PrimitiveDefinition> {
+        self.primitives_library.get(name)
+            .or_else(|| self.hidden_primitives_library.get(name))
+            .or_else(|| self.entities_library.values().map(|ent| &ent.primitive).find(|prim| prim.name == name))
+    }
+
+    // Let active entities do their thing, called before each step
+    fn update_entities( &mut self ) {
+        let mut ready = Vec::new();
+        let mut switches = Vec::new();
+        for (i, ent) in self.entities.iter_mut().enumerate() {
+            ent.cooldown = (ent.cooldown - self.timestep).max(0.0);
+            match &ent.entity_type {
+                EntityType::Cannon { fire_delay, .. } => {
+                    if ent.active && ent.cooldown <= 0.0 && ent.ammo > 0 {
+                        ent.ammo -= 1;
+                        ent.cooldown = *fire_delay;
+                        ready.push(i);
+                    }
+                },
+                // Only when the switch is flipped, so the motor can still be toggled by other means in between
+                EntityType::MotorSwitch { joint : Some(joint) } => {
+                    if ent.switched != Some(ent.active) {
+                        ent.switched = Some(ent.active);
+                        switches.push((i, *joint, ent.active));
+                    }
+                },
+                EntityType::MotorSwitch { joint : None } => {},
+            }
+        }
+        for (i, joint, enabled) in switches {
+            if !self.set_motor_enabled(joint, enabled) {
+                eprintln!("WARNING: {}: Joint {} doesn't have a motor", self.entities[i].name, joint);
+            }
+        }
+        for i in ready {
+            if let Err(e) = self.fire_cannon(i) {
+                eprintln!("WARNING: {} failed to fire: {}", self.entities[i].name, e);
+            }
+        }
+    }
+
+    // Spawn a cannon's projectile at its spawn point and give it a kick
+    // The spawn point and velocity are in the cannon's frame, so they follow it around
+    fn fire_cannon( &mut self, entity : usize ) -> Result<(), GoldbergError> {
+        let ent = &self.entities[entity];
+        let (spawn_point, spawn_velocity, projectile, projectile_scale) = match &ent.entity_type {
+            EntityType::Cannon { spawn_point, spawn_velocity, projectile, projectile_scale, .. } => (*spawn_point, *spawn_velocity, projectile.clone(), *projectile_scale),
+            _ => return Err(GoldbergError::Validation(format!("{}: Isn't a cannon", ent.name))),
+        };
+        let pose = match self.bodies.rigid_body(ent.body) {
+            Some(rb) => *rb.position(),
+            None => return Err(GoldbergError::Validation(format!("{}: Body has been removed", ent.name))),
+        };
+
+        let position = pose * Point3::from(Vector3::from(spawn_point));
+        let body = self.spawn_projectile(&projectile, projectile_scale, &Isometry3::from_parts(Translation3::from(position.coords), pose.rotation))?;
+
+        // A velocity change, so the projectile's mass and the timestep don't matter
+        let velocity = pose.rotation * Vector3::from(spawn_velocity);
+        if let Some(rb) = self.bodies.rigid_body_mut(body) {
+            rb.apply_force(0, &Force3::linear(velocity), ForceType::VelocityChange, true);
+        }
+        Ok(())
+    }
+
+    // Add a body from the hidden primitives library, on behalf of an entity
+    // Projectiles don't collide with cannons, so they can start off inside the barrel
+    fn spawn_projectile( &mut self, name : &str, scale : [f32; 3], pose : &Isometry3<f32> ) -> Result<DefaultBodyHandle, GoldbergError> {
+        let prim = self.hidden_primitives_library.get(name).ok_or_else(|| GoldbergError::UnknownPrimitive(String::from(name)))?;
+        let t = pose.translation.vector;
+        let r = pose.rotation.scaled_axis();
+        let mut placement = primitive_placement(&prim.name, [t.x, t.y, t.z], [r.x, r.y, r.z], false);
+        placement.scale = Some(scale);
+        let mut sim_prim = build_primitive_body(&mut self.bodies, &mut self.colliders, &self.body_volumes, prim, &placement)?;
+        sim_prim.spawned = true;
+        let body = sim_prim.body;
+        self.set_collision_groups(body, CollisionGroups::new().with_blacklist(&[CANNON_GROUP]));
+        self.primitives.push(sim_prim);
+        Ok(body)
+    }
+
+    // Remove everything entities have spawned
+    fn remove_spawned( &mut self ) {
+        for prim in self.primitives.iter().filter(|p| p.spawned) {
+            // Its colliders are removed by the world on the next step
+            self.bodies.remove(prim.body);
+            if let Ok(mut volumes) = self.body_volumes.write() {
+                volumes.remove(&prim.body);
+            }
+        }
+        self.primitives.retain(|p| !p.spawned);
+    }
+
+    fn set_collision_groups( &mut self, body : DefaultBodyHandle, groups : CollisionGroups ) {
+        for (_, collider) in self.colliders.iter_mut().filter(|(_, co)| co.body() == body) {
+            collider.set_collision_groups(groups);
+        }
+    }
+
+    // Advance the simulation by a period of (wall clock) time, in fixed size steps
+    // Any remainder is carried over to the next call, returns the number of steps taken
+    pub fn advance( &mut self, elapsed : f32 ) -> u32 {
+        self.accumulator += elapsed;
+        let mut steps = 0;
+        while self.accumulator >= self.timestep && steps < self.max_substeps {
+            self.step();
+            self.accumulator -= self.timestep;
+            steps += 1;
+        }
+        // If we can't keep up drop the backlog, better to run slow than take ever larger steps
+        // Only whole steps are dropped, the remainder still carries over
+        if self.accumulator >= self.timestep {
+            self.accumulator %= self.timestep;
+        }
+        steps
+    }
+
+    // Take a single step of exactly self.timestep
+    // Given the same level and sequence of steps the results are identical every time
+    pub fn step( &mut self ) {
+        self.update_entities();
+        self.apply_motors();
+        self.apply_hinge_limits();
+        if let Ok(mut gravity) = self.shared_gravity.write() {
+            *gravity = self.mechanical_world.gravity;
+        }
+        self.mechanical_world.set_timestep(self.timestep);
+        self.mechanical_world.step(
+            &mut self.geometrical_world,
+            &mut self.bodies,
+            &mut self.colliders,
+            &mut self.joint_constrants,
+            &mut self.force_generators,
+        );
+        self.step_count += 1;
+    }
+
+    // Simulated time, in seconds
+    pub fn time( &self ) -> f32 {
+        self.step_count as f32 * self.timestep
+    }
+
+    // Pose and velocity of every primitive, in the order they were added
+    pub fn body_states( &self ) -> Vec<BodyState> {
+        let mut results = Vec::new();
+        for prim in &self.primitives {
+            if let Some(rb) = self.bodies.rigid_body(prim.body) {
+                let pos = rb.position();
+                let vel = rb.velocity();
+                let rot = pos.rotation.scaled_axis();
+                results.push(BodyState {
+                    name : prim.name.clone(),
+                    position : [pos.translation.vector.x, pos.translation.vector.y, pos.translation.vector.z],
+                    rotation : [rot.x, rot.y, rot.z],
+                    linear_velocity : [vel.linear.x, vel.linear.y, vel.linear.z],
+                    angular_velocity : [vel.angular.x, vel.angular.y, vel.angular.z],
+                    sleeping : !rb.is_active(),
+                    spawned_scale : if prim.spawned { Some([prim.scale.x, prim.scale.y, prim.scale.z]) } else { None },
+                });
+            }
+        }
+        results
+    }
+
+    pub fn snapshot( &self, level : &str ) -> Snapshot {
+        Snapshot {
+            level : String::from(level),
+            step_count : self.step_count,
+            bodies : self.body_states(),
+            entities : self.entities.iter().map(|ent| EntityState {
+                active : ent.active,
+                ammo : ent.ammo,
+                cooldown : ent.cooldown,
+                switched : ent.switched,
+            }).collect(),
+            motors : self.motors.iter().map(|m| m.enabled).collect(),
+        }
+    }
+
+    // Restore body poses and velocities, and the state of entities and motors from a snapshot
+    // The snapshot must have been taken from the same level, anything spawned since is replaced by what was spawned then
+    pub fn restore_snapshot( &mut self, snapshot : &Snapshot ) -> Result<(), GoldbergError> {
+        let (placed, spawned) : (Vec<&BodyState>, Vec<&BodyState>) = snapshot.bodies.iter().partition(|b| b.spawned_scale.is_none());
+        let level_prims : Vec<&SimulationPrimitive> = self.primitives.iter().filter(|p| !p.spawned).collect();
+        if placed.len() != level_prims.len() {
+            return Err(GoldbergError::Validation(format!(
+                "Snapshot has {} bodies, simulation has {}", placed.len(), level_prims.len())));
+        }
+        for (i, (prim, state)) in level_prims.iter().zip(&placed).enumerate() {
+            if prim.name != state.name {
+                return Err(GoldbergError::Validation(format!(
+                    "Snapshot body {} is a {}, simulation has a {}", i, state.name, prim.name)));
+            }
+        }
+        if snapshot.entities.len() != self.entities.len() || snapshot.motors.len() != self.motors.len() {
+            return Err(GoldbergError::Validation(format!(
+                "Snapshot has {} entities and {} motors, simulation has {} and {}",
+                snapshot.entities.len(), snapshot.motors.len(), self.entities.len(), self.motors.len())));
+        }
+        if let Some(state) = spawned.iter().find(|b| !self.hidden_primitives_library.contains_key(&b.name)) {
+            return Err(GoldbergError::UnknownPrimitive(state.name.clone()));
+        }
+
+        // Placed bodies stay first, so the order matches the partitioned states
+        self.remove_spawned();
+        for state in &spawned {
+            let pose = Isometry3::new(Vector3::from(state.position), Vector3::from(state.rotation));
+            self.spawn_projectile(&state.name, state.spawned_scale.unwrap_or([1.0; 3]), &pose)?;
+        }
+        for (prim, state) in self.primitives.iter().zip(placed.iter().chain(&spawned)) {
+            if let Some(rb) = self.bodies.rigid_body_mut(prim.body) {
+                rb.set_position(Isometry3::new(Vector3::from(state.position), Vector3::from(state.rotation)));
+                rb.set_velocity(Velocity3::new(Vector3::from(state.linear_velocity), Vector3::from(state.angular_velocity)));
+                if state.sleeping {
+                    rb.deactivate();
+                } else {
+                    rb.activate();
+                }
+            }
+        }
+        for (ent, state) in self.entities.iter_mut().zip(&snapshot.entities) {
+            ent.active = state.active;
+            ent.ammo = state.ammo;
+            ent.cooldown = state.cooldown;
+            ent.switched = state.switched;
+        }
+        for (motor, enabled) in self.motors.iter_mut().zip(&snapshot.motors) {
+            motor.enabled = *enabled;
+        }
+        self.step_count = snapshot.step_count;
+        self.accumulator = 0.0;
+        Ok(())
+    }
+
+    // Current pose of a collider, if it's still in the world
+    pub fn collider_position( &self, collider : DefaultColliderHandle ) -> Option<Isometry3<f32>> {
+        self.colliders.get(collider).map(|co| *co.position())
+    }
+}
+
+#[derive(Debug,PartialEq,Clone,Copy)]
+pub enum SimulationMode {
+    Edit, // Bodies are frozen at their spawn positions
+    Play, // Physics running
+    Replay, // Bodies positioned from a recording, physics not running
+}
+
+pub struct AppState {
+    pub window : Window,
+    pub mode : SimulationMode,
+    pub assets_path : String,
+    pub sim : Simulation,
     pub physics_entities: Vec<PhysicsEntity>,
     pub simulation_start_time : Instant,
     pub simulation_last_update_ms: f32,
-    pub primitives_library : HashMap<String, PrimitiveDefinition>,
     pub camera : ArcBall,
     pub planar_camera : FixedView,
     // TODO: Having this here duplicates a load of stuff, but makes it easy to save the level at the end
     pub level_definition : LevelDefinition,
     pub level_file : String,
     pub render_debug_extents : bool,
+    // If set the simulation is being recorded, saved on exit
+    pub recording : Option<Trajectory>,
+    // Problems found while loading the primitive library, shown in the editor
+    pub diagnostics : Vec<String>,
+    // Problems found in the level, updated whenever it's (re)loaded
+    pub level_issues : Vec<LevelIssue>,
 }
 impl AppState {
 
-    pub fn add_primitive( &mut self, name : &str, position : &Vector3<f32>, rotation : &Vector3<f32>, static_object : bool ) -> bool {
-        // Log the primitive in the level definition
-        self.level_definition.primitives.push(
-            LevelPrimitiveDefinition{
-                name : String::from(name),
-                position : [position.x, position.y, position.z],
-                rotation : [rotation.x, rotation.y, rotation.z],
-                is_static: static_object,
-            }
-        );
-        self.add_primitive_without_adding_to_level(name, position, rotation, static_object)
-    }
-
-    fn add_primitive_without_adding_to_level( &mut self, name : &str, position : &Vector3<f32>, rotation : &Vector3<f32>, static_object : bool ) -> bool {
-        if let Some(prim) = self.primitives_library.get_mut(name) {
-            // Build the rigid body.
-            let prim_scale = Vector3::from(prim.scale);
-        
-            let mut body_status = BodyStatus::Dynamic;
-            if static_object {
-                body_status = BodyStatus::Static;
-            }
-
-            let rb = RigidBodyDesc::new()
-                .translation(*position)
-                .rotation(*rotation)
-                .status(body_status)
-                .build();
-            let rb_handle = self.bodies.insert(rb);
-        
-            let collider_shape;
-            let mut shapes = Vec::new();
-            // Iterate over each of the collider defs, make a cuboid for each
-            for cdef in &prim.collider_def {
-                let collider_pos = Vector3::new(
-                    cdef.origin[0] * prim_scale.x,
-                    cdef.origin[1] * prim_scale.y,
-                    cdef.origin[2] * prim_scale.z,
-                );
-                let collider_dim = Vector3::new(
-                    cdef.dimensions[0] * prim_scale.x,
-                    cdef.dimensions[1] * prim_scale.y,
-                    cdef.dimensions[2] * prim_scale.z,
-                );
-                let delta = Isometry3::new(collider_pos, na::zero());
-
-                match cdef.collider_type {
-                    ColliderType::Cuboid => {        
-                        shapes.push((delta, ShapeHandle::new(Cuboid::new(collider_dim))));
-                    },
-                    ColliderType::Ball => {
-                        shapes.push((delta, ShapeHandle::new(Ball::new(collider_dim.x))));
-                    }
-                }
-            }
-            collider_shape = ShapeHandle::new(Compound::new(shapes));
-            
-            // Build the collider.
-            let co = ColliderDesc::new(collider_shape)
-                .density(prim.density) // g/m^3
-                .material(MaterialHandle::new(BasicMaterial::new(prim.restitution, prim.friction))) // Restitution, Friction
-                // .margin( 0.000001 )
-                //.translation(collider_pos)
-                .ccd_enabled(false) // TODO: Enabling should provide better accuracy, but causes dominos on the floor to glitch out randomly
-                .build(BodyPartHandle(rb_handle, 0));
-        
-            let collision_handle = self.colliders.insert(co);
-            
-            let gfx = self.window.add_obj(
-                Path::new(&format!("{}/{}", self.assets_path, prim.path_obj)),
-                Path::new(&format!("{}/{}", self.assets_path, prim.path_mtl)),
-                prim_scale,
-            );
+    pub fn add_primitive( &mut self, name : &str, position : &Vector3<f32>, rotation : &Vector3<f32>, static_object : bool ) -> Result<(), GoldbergError> {
+        let def = primitive_placement(name, [position.x, position.y, position.z], [rotation.x, rotation.y, rotation.z], static_object);
+        self.add_primitive_without_adding_to_level(&def)?;
+        // Log the primitive in the level definition, only if it could be added
+        self.level_definition.primitives.push(def);
+        Ok(())
+    }
+
+    pub fn add_entity( &mut self, name : &str, position : &Vector3<f32>, rotation : &Vector3<f32>, static_object : bool ) -> Result<(), GoldbergError> {
+        let def = LevelEntityDefinition {
+            name : String::from(name),
+            position : [position.x, position.y, position.z],
+            rotation : [rotation.x, rotation.y, rotation.z],
+            is_static : static_object,
+            active_default : None,
+            parameters : serde_json::Map::new(),
+        };
+        self.sim.add_entity(&def)?;
+        self.add_physics_entities();
+        self.level_definition.entities.push(def);
+        Ok(())
+    }
+
+    fn add_primitive_without_adding_to_level( &mut self, def : &LevelPrimitiveDefinition ) -> Result<(), GoldbergError> {
+        self.sim.add_primitive(def)?;
+        self.add_physics_entities();
+        Ok(())
+    }
+
+    // Create scene nodes for any simulation primitives which don't have one yet
+    pub fn add_physics_entities( &mut self ) {
+        while self.physics_entities.len() < self.sim.primitives.len() {
+            let sim_prim = &self.sim.primitives[self.physics_entities.len()];
+            let (name, scale, colour, collider) = (sim_prim.name.clone(), sim_prim.scale, sim_prim.colour, sim_prim.collider);
+            let gfx = match self.add_primitive_node(&name, scale, colour) {
+                Some(x) => x,
+                None => return,
+            };
 
             self.physics_entities.push(PhysicsEntity{
-                collider : collision_handle,
+                collider,
                 //collider_origin : collider_pos,
                 node : gfx,
             });
+        }
+    }
+
+    // Scene node for a primitive, whether it was placed, is part of an entity or was spawned by one
+    pub fn add_primitive_node( &mut self, name : &str, scale : Vector3<f32>, colour : Option<[f32; 3]> ) -> Option<SceneNode> {
+        let prim = match self.sim.primitive_definition(name) {
+            Some(x) => x,
+            None => {
+                eprintln!("ERROR: No definition for primitive: {}", name);
+                return None;
+            }
+        };
+        let mut gfx = self.window.add_obj(
+            Path::new(&format!("{}/{}", self.assets_path, prim.path_obj)),
+            Path::new(&format!("{}/{}", self.assets_path, prim.path_mtl)),
+            scale,
+        );
+        if let Some(colour) = colour {
+            gfx.set_color(colour[0], colour[1], colour[2]);
+        }
+        Some(gfx)
+    }
+
+    // Recreate every scene node, after bodies have been removed from the simulation
+    pub fn rebuild_physics_entities( &mut self ) {
+        for ent in &mut self.physics_entities {
+            self.window.remove_node(&mut ent.node);
+        }
+        self.physics_entities.clear();
+        self.add_physics_entities();
+    }
 
-            return true;
+    // Move the scene nodes to match the simulation
+    pub fn sync_physics_entities( &mut self ) {
+        for ent in &mut self.physics_entities {
+            if let Some(pos) = self.sim.collider_position(ent.collider) {
+                // let collider_translation = Translation3::new(
+                //     - ent.collider_origin.x,
+                //     - ent.collider_origin.y,
+                //     - ent.collider_origin.z,
+                // );
+                // pos.append_translation_mut(&collider_translation);
+                ent.node.set_local_transformation(pos);
+            }
         }
-        false
     }
 
+    // Springs aren't bodies, so they're drawn as lines each frame
+    pub fn draw_springs( &mut self, endpoints : &[(Point3<f32>, Point3<f32>)] ) {
+        for (start, end) in endpoints {
+            self.window.draw_line(start, end, &Point3::new(1.0, 0.9, 0.1));
+        }
+    }
 
     pub fn draw_hud_text( &mut self, text : &str, position : &na::Point2<f32>, size: f32 ) {
         let font = Font::default();
         // Text coordinates are in pixels, from top-left
         self.window.draw_text(
-            &text,
-            &position,
+            text,
+            position,
             size,
             &font,
             &Point3::new(1.0, 1.0, 1.0),
         );
     }
 
+    // Throw away the running simulation and rebuild it from the level definition
+    pub fn reset_to_level( &mut self ) {
+        for ent in &mut self.physics_entities {
+            self.window.remove_node(&mut ent.node);
+        }
+        self.physics_entities.clear();
+        self.sim.clear();
+        self.add_primitives_from_level_definition();
+        self.mode = SimulationMode::Edit;
+        // Only the most recent run is kept
+        if self.recording.is_some() {
+            self.recording = Some(Trajectory::new(&self.level_definition.name));
+        }
+    }
+
     // TODO: Should just do this in a constructor or such
     pub fn add_primitives_from_level_definition(&mut self) {
-        // TODO: Hack around borrowing issues, should learn the correct pattern for this
-        let prims = self.level_definition.primitives.clone();
-        for prim in prims {
-            self.add_primitive_without_adding_to_level(&prim.name,&Vector3::from(prim.position), &Vector3::from(prim.rotation), prim.is_static);
+        self.level_issues = validate_level(&self.level_definition, &self.sim.primitives_library);
+        self.level_issues.append(&mut validate_entities(&self.level_definition, &self.sim.primitives_library, &self.sim.entities_library));
+        for e in self.sim.load_level(&self.level_definition) {
+            eprintln!("WARNING: Failed to add to level: {}", e);
+        }
+        self.add_physics_entities();
+    }
+}
+
+// Scale of a placed primitive, taking any override into account
+pub fn primitive_scale( prim : &PrimitiveDefinition, def : &LevelPrimitiveDefinition ) -> Vector3<f32> {
+    Vector3::from(def.scale.unwrap_or(prim.scale))
+}
+
+// Build the collision shapes for a primitive, relative to the primitive's origin
+// Everything is combined into a single compound shape, except triangle meshes which are returned separately
+pub fn primitive_collider_shapes( prim : &PrimitiveDefinition, prim_scale : &Vector3<f32> ) -> Result<Vec<ShapeHandle<f32>>, GoldbergError> {
+    let mut shapes = Vec::new();
+    let mut meshes = Vec::new();
+    // Iterate over each of the collider defs, make a shape for each
+    for cdef in &prim.collider_def {
+        let collider_pos = Vector3::new(
+            cdef.origin[0] * prim_scale.x,
+            cdef.origin[1] * prim_scale.y,
+            cdef.origin[2] * prim_scale.z,
+        );
+        let collider_dim = Vector3::new(
+            cdef.dimensions[0] * prim_scale.x,
+            cdef.dimensions[1] * prim_scale.y,
+            cdef.dimensions[2] * prim_scale.z,
+        );
+        // Scale is applied along the collider's own axes, before it's rotated
+        let collider_rot = UnitQuaternion::from_euler_angles(
+            cdef.rotation[0].to_radians(),
+            cdef.rotation[1].to_radians(),
+            cdef.rotation[2].to_radians(),
+        );
+        let delta = Isometry3::from_parts(Translation3::from(collider_pos), collider_rot);
+
+        match cdef.collider_type {
+            ColliderType::Cuboid => {        
+                shapes.push((delta, ShapeHandle::new(Cuboid::new(collider_dim))));
+            },
+            ColliderType::Ball => {
+                shapes.push((delta, ShapeHandle::new(Ball::new(collider_dim.x))));
+            },
+            ColliderType::Capsule => {
+                shapes.push((delta, ShapeHandle::new(Capsule::new(collider_dim.y, collider_dim.x))));
+            },
+            // ncollide doesn't have cylinder/cone contacts, so approximate them with a hull
+            ColliderType::Cylinder => {
+                let mut points = round_points(collider_dim.x, -collider_dim.y);
+                points.append(&mut round_points(collider_dim.x, collider_dim.y));
+                shapes.push((delta, convex_hull_shape(&prim.name, &points)?));
+            },
+            ColliderType::Cone => {
+                let mut points = round_points(collider_dim.x, -collider_dim.y);
+                points.push(Point3::new(0.0, collider_dim.y, 0.0));
+                shapes.push((delta, convex_hull_shape(&prim.name, &points)?));
+            },
+            ColliderType::ConvexHull => {
+                let points : Vec<Point3<f32>> = cdef.points.iter()
+                    .map(|p| Point3::new(p[0] * prim_scale.x, p[1] * prim_scale.y, p[2] * prim_scale.z))
+                    .collect();
+                shapes.push((delta, convex_hull_shape(&prim.name, &points)?));
+            },
+            // Compounds of meshes don't collide well, so bake the offset into the mesh instead
+            ColliderType::TriMesh => {
+                let points : Vec<Point3<f32>> = cdef.points.iter()
+                    .map(|p| delta * Point3::new(p[0] * prim_scale.x, p[1] * prim_scale.y, p[2] * prim_scale.z))
+                    .collect();
+                let indices : Vec<Point3<usize>> = cdef.triangles.iter()
+                    .map(|t| Point3::new(t[0], t[1], t[2]))
+                    .collect();
+                if indices.is_empty() || cdef.triangles.iter().any(|t| t.iter().any(|i| *i >= points.len())) {
+                    return Err(GoldbergError::Validation(format!("{}: Invalid triangle mesh collider", prim.name)));
+                }
+                meshes.push(ShapeHandle::new(TriMesh::new(points, indices, None)));
+            },
+        }
+    }
+
+    let mut results = Vec::new();
+    if !shapes.is_empty() {
+        results.push(ShapeHandle::new(Compound::new(shapes)));
+    }
+    results.append(&mut meshes);
+    if results.is_empty() {
+        return Err(GoldbergError::Validation(format!("{}: No colliders defined", prim.name)));
+    }
+    Ok(results)
+}
+
+type JointAxes = (Unit<Vector3<f32>>, Unit<Vector3<f32>>);
+
+// A world space joint axis in the frame of each body
+fn joint_axes( axis : &[f32; 3], pose1 : &Isometry3<f32>, pose2 : &Isometry3<f32> ) -> Result<JointAxes, GoldbergError> {
+    let axis = Vector3::from(*axis);
+    if axis.norm() == 0.0 {
+        return Err(GoldbergError::Validation(String::from("Axis has no length")));
+    }
+    Ok((
+        Unit::new_normalize(pose1.inverse_transform_vector(&axis)),
+        Unit::new_normalize(pose2.inverse_transform_vector(&axis)),
+    ))
+}
+
+// Any direction at right angles to an axis
+fn perpendicular( axis : &Vector3<f32> ) -> Vector3<f32> {
+    let other = if axis.x.abs() < 0.9 { Vector3::x() } else { Vector3::y() };
+    axis.cross(&other).normalize()
+}
+
+// An entity's parameters, with a placement's overrides applied on top
+pub fn entity_parameters( ent : &EntityDefinition, def : &LevelEntityDefinition ) -> Result<EntityType, GoldbergError> {
+    let mut value = serde_json::to_value(&ent.entity_type).map_err(|e| GoldbergError::parse(&def.name, e))?;
+    if let Value::Object(params) = &mut value {
+        for (key, param) in &def.parameters {
+            if key == "type" || !params.contains_key(key) {
+                return Err(GoldbergError::Validation(format!("{}: Can't override parameter: {}", def.name, key)));
+            }
+            params.insert(key.clone(), param.clone());
+        }
+    }
+    serde_json::from_value(value).map_err(|e| GoldbergError::Validation(format!("{}: Invalid parameter override: {}", def.name, e)))
+}
+
+// Placement of a primitive without any overrides
+pub fn primitive_placement( name : &str, position : [f32; 3], rotation : [f32; 3], is_static : bool ) -> LevelPrimitiveDefinition {
+    LevelPrimitiveDefinition {
+        name : String::from(name),
+        position,
+        rotation,
+        is_static,
+        scale : None,
+        density : None,
+        friction : None,
+        restitution : None,
+        colour : None,
+        linear_velocity : None,
+        angular_velocity : None,
+    }
+}
+
+// Add the rigid body and colliders for a primitive to the world
+// Shared by placed primitives, entities and anything entities spawn
+fn build_primitive_body( bodies : &mut DefaultBodySet<f32>, colliders : &mut DefaultColliderSet<f32>, volumes : &BodyVolumes, prim : &PrimitiveDefinition, def : &LevelPrimitiveDefinition ) -> Result<SimulationPrimitive, GoldbergError> {
+    if !def.is_static && primitive_has_trimesh(prim) {
+        return Err(GoldbergError::Validation(format!(
+            "{}: Triangle mesh colliders can only be used on static bodies", def.name)));
+    }
+    let scale = primitive_scale(prim, def);
+    let collider_shapes = primitive_collider_shapes(prim, &scale)?;
+
+    // Build the rigid body.
+    let mut body_status = BodyStatus::Dynamic;
+    if def.is_static {
+        body_status = BodyStatus::Static;
+    }
+
+    let velocity = Velocity3::new(
+        Vector3::from(def.linear_velocity.unwrap_or([0.0; 3])),
+        Vector3::from(def.angular_velocity.unwrap_or([0.0; 3])),
+    );
+    let rb = RigidBodyDesc::new()
+        .translation(Vector3::from(def.position))
+        .rotation(Vector3::from(def.rotation))
+        .velocity(velocity)
+        .status(body_status)
+        .build();
+    let rb_handle = bodies.insert(rb);
+    if !def.is_static {
+        if let Ok(mut volumes) = volumes.write() {
+            volumes.insert(rb_handle, volume_samples(&collider_shapes));
+        }
+    }
+
+    let restitution = def.restitution.unwrap_or(prim.restitution);
+    let friction = def.friction.unwrap_or(prim.friction);
+
+    // Build the colliders.
+    let mut collision_handles = Vec::new();
+    for collider_shape in collider_shapes {
+        let co = ColliderDesc::new(collider_shape)
+            .density(def.density.unwrap_or(prim.density)) // g/m^3
+            .material(MaterialHandle::new(BasicMaterial::new(restitution, friction))) // Restitution, Friction
+            // .margin( 0.000001 )
+            //.translation(collider_pos)
+            .ccd_enabled(false) // TODO: Enabling should provide better accuracy, but causes dominos on the floor to glitch out randomly
+            .build(BodyPartHandle(rb_handle, 0));
+        collision_handles.push(colliders.insert(co));
+    }
+
+    Ok(SimulationPrimitive{
+        name : def.name.clone(),
+        scale,
+        colour : def.colour,
+        body : rb_handle,
+        collider : collision_handles[0],
+        spawned : false,
+    })
+}
+
+pub fn primitive_has_trimesh( prim : &PrimitiveDefinition ) -> bool {
+    prim.collider_def.iter().any(|cdef| matches!(cdef.collider_type, ColliderType::TriMesh))
+}
+
+// Number of points around the edge of cylinders/cones
+const ROUND_SEGMENTS : usize = 16;
+
+// Circle of points in the xz plane
+fn round_points( radius : f32, y : f32 ) -> Vec<Point3<f32>> {
+    (0..ROUND_SEGMENTS).map(|i| {
+        let angle = i as f32 * std::f32::consts::PI * 2.0 / ROUND_SEGMENTS as f32;
+        Point3::new(radius * angle.cos(), y, radius * angle.sin())
+    }).collect()
+}
+
+fn convex_hull_shape( prim_name : &str, points : &[Point3<f32>] ) -> Result<ShapeHandle<f32>, GoldbergError> {
+    match ConvexHull::try_from_points(points) {
+        Some(x) => Ok(ShapeHandle::new(x)),
+        None => Err(GoldbergError::Validation(format!(
+            "{}: Can't build a convex hull from {} points, are they all flat?", prim_name, points.len()))),
+    }
+}
+
+// Fill in the geometry of any ConvexHull/TriMesh colliders which should be built from the .obj
+fn resolve_mesh_colliders( prim : &mut PrimitiveDefinition, assets_path : &str ) -> Result<(), GoldbergError> {
+    let mut mesh = None;
+    for cdef in &mut prim.collider_def {
+        let needs_mesh = match cdef.collider_type {
+            ColliderType::ConvexHull => cdef.points.is_empty(),
+            ColliderType::TriMesh => cdef.points.is_empty() && cdef.triangles.is_empty(),
+            _ => false,
+        };
+        if !needs_mesh {
+            continue;
+        }
+        if mesh.is_none() {
+            mesh = Some(load_obj(&format!("{}/{}", assets_path, prim.path_obj))?);
+        }
+        if let Some(m) = &mesh {
+            cdef.points = m.vertices.iter().map(|v| [v.x, v.y, v.z]).collect();
+            if let ColliderType::TriMesh = cdef.collider_type {
+                cdef.triangles = m.triangles.clone();
+            }
+        }
+    }
+    Ok(())
+}
+
+// Thickness of the ground slab, top surface is at y = 0
+pub const GROUND_THICKNESS : f32 = 0.1;
+
+pub fn ground_collision_cuboid( level : &LevelDefinition ) -> Cuboid<f32> {
+    Cuboid::new(
+        Vector3::new(level.ground_dimensions[0] / 2.0, GROUND_THICKNESS / 2.0, level.ground_dimensions[1] / 2.0)
+    )
+}
+
+impl Trajectory {
+    pub fn new( level : &str ) -> Self {
+        Trajectory {
+            level : String::from(level),
+            bodies : Vec::new(),
+            scales : Vec::new(),
+            frames : Vec::new(),
+        }
+    }
+
+    // Record the current pose of every primitive in the simulation
+    pub fn record_frame( &mut self, sim : &Simulation ) {
+        while self.bodies.len() < sim.primitives.len() {
+            let prim = &sim.primitives[self.bodies.len()];
+            self.bodies.push(prim.name.clone());
+            self.scales.push([prim.scale.x, prim.scale.y, prim.scale.z]);
+        }
+
+        let mut poses = Vec::new();
+        for prim in &sim.primitives {
+            let pos = sim.collider_position(prim.collider).unwrap_or_else(Isometry3::identity);
+            let t = pos.translation.vector;
+            let r = pos.rotation.quaternion();
+            poses.push([t.x, t.y, t.z, r.i, r.j, r.k, r.w]);
+        }
+        self.frames.push(TrajectoryFrame {
+            time : sim.time(),
+            poses,
+        });
+    }
+
+    pub fn start_time( &self ) -> f32 {
+        self.frames.first().map_or(0.0, |f| f.time)
+    }
+
+    pub fn end_time( &self ) -> f32 {
+        self.frames.last().map_or(0.0, |f| f.time)
+    }
+
+    // Pose of a body at the given time, interpolated between frames
+    // None if the body didn't exist at that time
+    pub fn pose_at( &self, body : usize, time : f32 ) -> Option<Isometry3<f32>> {
+        if self.frames.is_empty() {
+            return None;
+        }
+        // Index of the first frame after time
+        let next = match self.frames.binary_search_by(|f| f.time.partial_cmp(&time).unwrap()) {
+            Ok(i) => i + 1,
+            Err(i) => i,
+        };
+        let prev = if next == 0 { 0 } else { next - 1 };
+        let next = next.min(self.frames.len() - 1);
+
+        let a = trajectory_pose(self.frames[prev].poses.get(body)?);
+        let b = match self.frames[next].poses.get(body) {
+            Some(x) => trajectory_pose(x),
+            None => return Some(a),
+        };
+
+        let span = self.frames[next].time - self.frames[prev].time;
+        if span <= 0.0 {
+            return Some(a);
         }
+        let t = ((time - self.frames[prev].time) / span).clamp(0.0, 1.0);
+        let translation = a.translation.vector.lerp(&b.translation.vector, t);
+        let rotation = a.rotation.slerp(&b.rotation, t);
+        Some(Isometry3::from_parts(Translation3::from(translation), rotation))
     }
 }
 
+fn trajectory_pose( pose : &[f32; 7] ) -> Isometry3<f32> {
+    Isometry3::from_parts(
+        Translation3::new(pose[0], pose[1], pose[2]),
+        UnitQuaternion::new_normalize(Quaternion::new(pose[6], pose[3], pose[4], pose[5])),
+    )
+}
+
 // Engine + functions
-pub fn load_primitives_definitions( assets_path : &String ) -> io::Result<HashMap<String, PrimitiveDefinition>> {
+fn load_json<T : DeserializeOwned>( file : &str ) -> Result<T, GoldbergError> {
+    let text = read_json_file(file)?;
+    serde_json::from_str(&text).map_err(|e| GoldbergError::parse(file, e))
+}
+
+fn read_json_file( file : &str ) -> Result<String, GoldbergError> {
+    let path = Path::new(file);
+    if !path.is_file() {
+        return Err(GoldbergError::io(file, io::Error::new(io::ErrorKind::NotFound, "File not found")));
+    }
+    match path.extension() {
+        Some(x) if x == "json" => {},
+        _ => return Err(GoldbergError::UnsupportedFile { path : String::from(file) }),
+    }
+
+    fs::read_to_string(path).map_err(|e| GoldbergError::io(file, e))
+}
+
+// Load json which has a format_version, upgrading it to the current version if needed
+// Returns true if it was upgraded
+fn load_versioned_json<T : DeserializeOwned>( file : &str, kind : FormatKind ) -> Result<(T, bool), GoldbergError> {
+    let text = read_json_file(file)?;
+    let mut value : Value = serde_json::from_str(&text).map_err(|e| GoldbergError::parse(file, e))?;
+    let upgraded = migrate(&mut value, kind, file)?;
+    if !upgraded {
+        // Straight from the text, so errors point at the right line
+        let result = serde_json::from_str(&text).map_err(|e| GoldbergError::parse(file, e))?;
+        return Ok((result, false));
+    }
+
+    // The original lines don't match up any more, so errors point into the file as it'd be saved once upgraded
+    let upgraded_text = serde_json::to_string_pretty(&value).map_err(|e| GoldbergError::serialize(file, e))?;
+    let result = serde_json::from_str(&upgraded_text).map_err(|e| GoldbergError::Parse {
+        path : String::from(file),
+        line : e.line(),
+        column : e.column(),
+        message : format!("{} (in the file upgraded to format version {})", e, kind.current_version()),
+    })?;
+    Ok((result, true))
+}
+
+fn save_json<T : Serialize>( value : &T, file : &str, pretty : bool ) -> Result<(), GoldbergError> {
+    let json_result = if pretty {
+        serde_json::to_string_pretty(value)
+    } else {
+        serde_json::to_string(value)
+    };
+    let json_str = json_result.map_err(|e| GoldbergError::serialize(file, e))?;
+    let mut json_file = File::create(file).map_err(|e| GoldbergError::io(file, e))?;
+    json_file.write_all(json_str.as_bytes()).map_err(|e| GoldbergError::io(file, e))
+}
+
+// Every .json file in a directory, sorted so any duplicates are resolved the same way every time
+// Entries which can't be read are skipped, returning an error for each one
+fn json_files_in( dir : &str, diagnostics : &mut Vec<GoldbergError> ) -> Result<Vec<String>, GoldbergError> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(Path::new(dir)).map_err(|e| GoldbergError::io(dir, e))? {
+        match entry {
+            Ok(x) => paths.push(x.path()),
+            Err(e) => diagnostics.push(GoldbergError::io(dir, e)),
+        }
+    }
+    paths.sort();
+    Ok(paths.iter()
+        .filter(|path| path.is_file() && path.extension().is_some_and(|x| x == "json"))
+        .map(|path| path.to_string_lossy().to_string())
+        .collect())
+}
+
+// Load every primitive in a directory of the assets dir
+// Broken files and duplicate names are skipped, returning an error for each one
+fn load_primitives_in( assets_path : &str, dir : &str ) -> Result<(HashMap<String, PrimitiveDefinition>, Vec<GoldbergError>), GoldbergError> {
     let mut results = HashMap::new();
-    let prim_path = format!("{}{}", assets_path, "/primitives");
-    let primitives_path = Path::new(&prim_path);
-    for entry in fs::read_dir(primitives_path)? {
-        let entry = entry?;
-        let path = entry.path();
-        // println!("Load primitive: {}", path.to_string_lossy());
-        if path.is_file() {
-            let ext = match path.extension() {
-                Some(x) => x,
-                None => continue
-            };
-            if ext == "json" {
-                let json_file = File::open(path)?;
-                let reader = BufReader::new(json_file);
-                let prim : PrimitiveDefinition = serde_json::from_reader(reader)?;
-                results.insert(prim.name.clone(), prim);
+    let mut diagnostics = Vec::new();
+    let mut result_paths : HashMap<String, String> = HashMap::new();
+
+    for path_str in json_files_in(&format!("{}/{}", assets_path, dir), &mut diagnostics)? {
+        // println!("Load primitive: {}", path_str);
+        let mut prim : PrimitiveDefinition = match load_versioned_json(&path_str, FormatKind::Primitive) {
+            Ok((x, _)) => x,
+            Err(e) => {
+                diagnostics.push(e);
+                continue;
             }
+        };
+        // Make sure the colliders can actually be built, rather than failing when one is placed
+        let shape_check = resolve_mesh_colliders(&mut prim, assets_path)
+            .and_then(|_| primitive_collider_shapes(&prim, &Vector3::from(prim.scale)));
+        if let Err(e) = shape_check {
+            diagnostics.push(e);
+            continue;
         }
+        if let Some(first_path) = result_paths.get(&prim.name) {
+            diagnostics.push(GoldbergError::DuplicateName {
+                name : prim.name.clone(),
+                path : path_str,
+                first_path : first_path.clone(),
+            });
+            continue;
+        }
+        result_paths.insert(prim.name.clone(), path_str);
+        results.insert(prim.name.clone(), prim);
     }
 
-    Ok(results)
+    Ok((results, diagnostics))
 }
 
-pub fn load_level_definition( level_file : &String ) -> Option<LevelDefinition> {
-    let path = Path::new(level_file);
-    if !path.is_file() { return None; }
-    
-    let ext = match path.extension() {
-        Some(x) => x,
-        None => return None
-    };
-    if ext != "json" { return None; }
-    
-    let json_file = match File::open(path) {
-        Ok(x) => x,
-        _ => return None
-    };
-    let reader = BufReader::new(json_file);
-    let level : LevelDefinition = match serde_json::from_reader(reader) {
-        Ok(x) => x,
-        _ => return None
-    };
-    Some(level)
+// Primitives which can be placed in levels
+pub fn load_primitives_definitions( assets_path : &str ) -> Result<(HashMap<String, PrimitiveDefinition>, Vec<GoldbergError>), GoldbergError> {
+    load_primitives_in(assets_path, "primitives")
+}
+
+// Primitives which are only spawned by entities, e.g. cannon balls
+pub fn load_hidden_primitives_definitions( assets_path : &str ) -> Result<(HashMap<String, PrimitiveDefinition>, Vec<GoldbergError>), GoldbergError> {
+    load_primitives_in(assets_path, "primitives_hidden")
+}
+
+// Load every entity in the assets dir, same as for primitives
+pub fn load_entities_definitions( assets_path : &str ) -> Result<(HashMap<String, EntityDefinition>, Vec<GoldbergError>), GoldbergError> {
+    let mut results = HashMap::new();
+    let mut diagnostics = Vec::new();
+    let mut result_paths : HashMap<String, String> = HashMap::new();
+
+    for path_str in json_files_in(&format!("{}/{}", assets_path, "entities"), &mut diagnostics)? {
+        let mut ent : EntityDefinition = match load_versioned_json(&path_str, FormatKind::Entity) {
+            Ok((x, _)) => x,
+            Err(e) => {
+                diagnostics.push(e);
+                continue;
+            }
+        };
+        let shape_check = resolve_mesh_colliders(&mut ent.primitive, assets_path)
+            .and_then(|_| primitive_collider_shapes(&ent.primitive, &Vector3::from(ent.primitive.scale)));
+        if let Err(e) = shape_check {
+            diagnostics.push(e);
+            continue;
+        }
+        if let Some(first_path) = result_paths.get(&ent.name) {
+            diagnostics.push(GoldbergError::DuplicateName {
+                name : ent.name.clone(),
+                path : path_str,
+                first_path : first_path.clone(),
+            });
+            continue;
+        }
+        result_paths.insert(ent.name.clone(), path_str);
+        results.insert(ent.name.clone(), ent);
+    }
+
+    Ok((results, diagnostics))
+}
+
+pub fn load_primitive_definition( primitive_file : &str ) -> Result<PrimitiveDefinition, GoldbergError> {
+    Ok(load_versioned_json(primitive_file, FormatKind::Primitive)?.0)
+}
+
+pub fn save_primitive_definition( prim : &PrimitiveDefinition, primitive_file : &str ) -> Result<(), GoldbergError> {
+    save_json(prim, primitive_file, true)
+}
+
+pub fn load_level_definition( level_file : &str ) -> Result<LevelDefinition, GoldbergError> {
+    Ok(load_versioned_json(level_file, FormatKind::Level)?.0)
 }
 
 pub fn load_level_empty( name : &str ) -> Option<LevelDefinition> {
     Some(LevelDefinition {
+        format_version : LEVEL_FORMAT_VERSION,
         name : String::from(name),
         ground_dimensions : [100.0, 100.0],
         ground_colour : [0.9, 0.9, 0.9],
         background_colour : [0.1,0.1,0.1],
+        world : WorldSettings::default(),
         primitives : Vec::new(),
+        entities : Vec::new(),
+        joints : Vec::new(),
+        force_zones : Vec::new(),
+        fluids : Vec::new(),
+        springs : Vec::new(),
     })
 }
 
-pub fn save_level_definition( level : &LevelDefinition, level_file : &String ) {
-    let level_str = serde_json::to_string_pretty(level).unwrap();
-    let mut file = match File::create(level_file) {
-        Ok(x) => x,
-        Err(e) => {
-            println!("ERROR: Failed to save level: {:?}", e);
-            return;
-        }
+pub fn save_level_definition( level : &LevelDefinition, level_file : &str ) -> Result<(), GoldbergError> {
+    save_json(level, level_file, true)
+}
+
+pub fn load_entity_definition( entity_file : &str ) -> Result<EntityDefinition, GoldbergError> {
+    Ok(load_versioned_json(entity_file, FormatKind::Entity)?.0)
+}
+
+// Re-save a level, primitive or entity file in the current format if it's from an older version
+// Returns true if the file was upgraded
+pub fn upgrade_file( file : &str, kind : FormatKind ) -> Result<bool, GoldbergError> {
+    let upgraded = match kind {
+        FormatKind::Level => {
+            let (level, upgraded) = load_versioned_json::<LevelDefinition>(file, kind)?;
+            if upgraded { save_json(&level, file, true)?; }
+            upgraded
+        },
+        FormatKind::Primitive => {
+            let (prim, upgraded) = load_versioned_json::<PrimitiveDefinition>(file, kind)?;
+            if upgraded { save_json(&prim, file, true)?; }
+            upgraded
+        },
+        FormatKind::Entity => {
+            let (ent, upgraded) = load_versioned_json::<EntityDefinition>(file, kind)?;
+            if upgraded { save_json(&ent, file, true)?; }
+            upgraded
+        },
     };
-    file.write_all(level_str.as_bytes()).unwrap();
+    Ok(upgraded)
+}
+
+pub fn load_trajectory( trajectory_file : &str ) -> Result<Trajectory, GoldbergError> {
+    load_json(trajectory_file)
+}
+
+pub fn save_trajectory( trajectory : &Trajectory, trajectory_file : &str ) -> Result<(), GoldbergError> {
+    // Not pretty printed, these get big
+    save_json(trajectory, trajectory_file, false)
+}
+
+// Snapshots are saved next to the level, <level>.snapshot.json
+pub fn snapshot_file_for_level( level_file : &str ) -> String {
+    match level_file.strip_suffix(".json") {
+        Some(x) => format!("{}.snapshot.json", x),
+        None => format!("{}.snapshot.json", level_file),
+    }
+}
+
+pub fn load_snapshot( snapshot_file : &str ) -> Result<Snapshot, GoldbergError> {
+    load_json(snapshot_file)
+}
+
+pub fn save_snapshot( snapshot : &Snapshot, snapshot_file : &str ) -> Result<(), GoldbergError> {
+    save_json(snapshot, snapshot_file, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Write a file into the temp dir for a test, named so tests don't trip over each other
+    fn temp_file( name : &str, contents : &str ) -> String {
+        let path = std::env::temp_dir().join(format!("goldberg-{}-{}", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    // Simulation with the shipped libraries, tests run from the crate root
+    fn test_sim() -> Simulation {
+        let mut sim = Simulation::new(load_primitives_definitions("assets/").unwrap().0);
+        sim.hidden_primitives_library = load_hidden_primitives_definitions("assets/").unwrap().0;
+        sim.entities_library = load_entities_definitions("assets/").unwrap().0;
+        sim
+    }
+
+    // A few primitives dropped on top of each other, so there's plenty of contacts
+    fn tumbling_level() -> LevelDefinition {
+        let mut level = load_level_empty("tumbling").unwrap();
+        level.primitives = vec![
+            primitive_placement("cubey-1x1", [0.0, 3.0, 0.0], [0.3, 0.2, 0.1], false),
+            primitive_placement("cubey-1x1", [0.4, 5.0, 0.2], [0.0, 0.7, 0.4], false),
+            primitive_placement("Ball-1m", [-0.3, 7.0, 0.1], [0.0; 3], false),
+            primitive_placement("domino-1x2", [1.0, 1.0, 0.0], [0.0, 0.5, 0.0], false),
+        ];
+        level
+    }
+
+    #[test]
+    fn frame_times_dont_change_the_result() {
+        let level = tumbling_level();
+        // Slices are in steps, running until the same number of steps have been taken
+        let run = |slices : &[f32]| {
+            let mut sim = test_sim();
+            assert!(sim.load_level(&level).is_empty());
+            let mut frame = 0;
+            while sim.step_count < 120 {
+                sim.advance(slices[frame % slices.len()] * sim.timestep);
+                frame += 1;
+            }
+            assert_eq!(sim.step_count, 120);
+            serde_json::to_string(&sim.body_states()).unwrap()
+        };
+        let expected = run(&[1.0]);
+        assert_eq!(run(&[0.3, 0.45, 0.7, 0.25]), expected);
+        assert_eq!(run(&[2.0]), expected);
+    }
+
+    // The tumbling level, plus a cannon firing three shots and a motorised platform behind a switch
+    fn machine_level() -> LevelDefinition {
+        let mut level = tumbling_level();
+        level.primitives.push(primitive_placement("domino-2x4", [10.0, 3.0, 0.0], [0.0; 3], false));
+        level.joints.push(LevelJointDefinition {
+            joint_type : JointType::Revolute {
+                axis : [0.0, 1.0, 0.0],
+                limits : None,
+                motor : Some(MotorDefinition { target_velocity : 1.0, max_force : 1.0e6, enabled : true }),
+            },
+            body1 : level.primitives.len() - 1,
+            body2 : None,
+            anchor : [10.0, 3.0, 0.0],
+        });
+        let entity = |name : &str, position, parameters : Value| LevelEntityDefinition {
+            name : String::from(name),
+            position,
+            rotation : [0.0; 3],
+            is_static : true,
+            active_default : Some(true),
+            parameters : parameters.as_object().unwrap().clone(),
+        };
+        level.entities.push(entity("Cannon-2m", [-10.0, 0.0, 0.0], serde_json::json!({ "ammo" : 3, "fire_delay" : 0.5 })));
+        level.entities.push(entity("motor-switch", [10.0, 0.0, 10.0], serde_json::json!({ "joint" : 0 })));
+        level
+    }
+
+    // Step the machine level, flipping the switch off part way through
+    fn play( sim : &mut Simulation, steps : u64 ) {
+        for _ in 0..steps {
+            if sim.step_count == 50 {
+                sim.entities[1].active = false;
+            }
+            sim.step();
+        }
+    }
+
+    #[test]
+    fn restored_snapshots_carry_on_the_same() {
+        let mut sim = test_sim();
+        assert!(sim.load_level(&machine_level()).is_empty());
+        play(&mut sim, 40);
+        // Through json, as it would be saved
+        let snapshot : Snapshot = serde_json::from_str(&serde_json::to_string(&sim.snapshot("machine")).unwrap()).unwrap();
+        assert_eq!(snapshot.bodies.iter().filter(|b| b.spawned_scale.is_some()).count(), 2);
+
+        play(&mut sim, 40);
+        let expected = sim.snapshot("machine");
+        assert_eq!(expected.bodies.iter().filter(|b| b.spawned_scale.is_some()).count(), 3);
+        assert_eq!(expected.entities[0].ammo, 0);
+        assert_eq!(expected.motors, vec![false]);
+
+        sim.restore_snapshot(&snapshot).unwrap();
+        assert_eq!(sim.entities[0].ammo, 1);
+        assert!(sim.motors[0].enabled);
+        play(&mut sim, 40);
+        let result = sim.snapshot("machine");
+        assert_eq!(result.step_count, expected.step_count);
+        assert_eq!(serde_json::to_value(&result.entities).unwrap(), serde_json::to_value(&expected.entities).unwrap());
+        assert_eq!(result.motors, expected.motors);
+        assert_eq!(result.bodies.len(), expected.bodies.len());
+        // The solver's contact cache isn't part of a snapshot, so bodies resting on each other come out very slightly differently
+        for (a, b) in result.bodies.iter().zip(&expected.bodies) {
+            assert_eq!((&a.name, a.spawned_scale, a.sleeping), (&b.name, b.spawned_scale, b.sleeping));
+            let values = |s : &BodyState| [s.position, s.rotation, s.linear_velocity, s.angular_velocity].concat();
+            for (x, y) in values(a).iter().zip(&values(b)) {
+                assert!((x - y).abs() < 0.05, "{}: {:?} vs {:?}", a.name, a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn type_errors_report_their_line() {
+        let file = temp_file("bad-level.json", &format!(r#"{{
+  "format_version": {},
+  "name": "bad",
+  "ground_dimensions": [10.0, 10.0],
+  "ground_colour": "red",
+  "background_colour": [0.1, 0.1, 0.1],
+  "primitives": []
+}}"#, LEVEL_FORMAT_VERSION));
+        let result = load_level_definition(&file);
+        fs::remove_file(&file).unwrap();
+        match result {
+            Err(GoldbergError::Parse { line, .. }) => assert_eq!(line, 5),
+            Err(e) => panic!("Expected a parse error, got: {}", e),
+            Ok(_) => panic!("Expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn upgraded_files_report_where_in_the_upgrade() {
+        let file = temp_file("bad-primitive.json", r#"{ "name": "bad", "density": "heavy" }"#);
+        let result = load_primitive_definition(&file);
+        fs::remove_file(&file).unwrap();
+        match result {
+            Err(GoldbergError::Parse { line, message, .. }) => {
+                assert!(line > 1);
+                assert!(message.contains("upgraded"), "{}", message);
+            },
+            Err(e) => panic!("Expected a parse error, got: {}", e),
+            Ok(_) => panic!("Expected a parse error"),
+        }
+    }
 }
@@ -0,0 +1,68 @@
+use std::fmt;
+use std::io;
+
+// Everything that can go wrong loading/saving levels, primitives and entities
+#[derive(Debug)]
+pub enum GoldbergError {
+    // Failed to read or write a file
+    Io { path : String, source : io::Error },
+    // File isn't valid json, or doesn't match the definition's structure
+    // line/column are 1-based, 0 if the location isn't known
+    Parse { path : String, line : usize, column : usize, message : String },
+    // Definition couldn't be turned into json to save it
+    Serialize { path : String, source : serde_json::Error },
+    // File isn't one we know how to load (wrong extension)
+    UnsupportedFile { path : String },
+    // A primitive name which isn't in the library
+    UnknownPrimitive(String),
+    // An entity name which isn't in the library
+    UnknownEntity(String),
+    // Two definitions with the same name, the first one loaded is kept
+    DuplicateName { name : String, path : String, first_path : String },
+    // Data which loaded but doesn't make sense
+    Validation(String),
+}
+
+impl GoldbergError {
+    pub fn io( path : &str, source : io::Error ) -> Self {
+        GoldbergError::Io { path : String::from(path), source }
+    }
+
+    pub fn parse( path : &str, e : serde_json::Error ) -> Self {
+        GoldbergError::Parse {
+            path : String::from(path),
+            line : e.line(),
+            column : e.column(),
+            message : e.to_string(),
+        }
+    }
+
+    pub fn serialize( path : &str, source : serde_json::Error ) -> Self {
+        GoldbergError::Serialize { path : String::from(path), source }
+    }
+}
+
+impl fmt::Display for GoldbergError {
+    fn fmt( &self, f : &mut fmt::Formatter ) -> fmt::Result {
+        match self {
+            GoldbergError::Io { path, source } => write!(f, "{}: {}", path, source),
+            GoldbergError::Parse { path, message, .. } => write!(f, "{}: {}", path, message),
+            GoldbergError::Serialize { path, source } => write!(f, "{}: Couldn't convert to json: {}", path, source),
+            GoldbergError::UnsupportedFile { path } => write!(f, "{}: Not a .json file", path),
+            GoldbergError::UnknownPrimitive(name) => write!(f, "Unknown primitive: {}", name),
+            GoldbergError::UnknownEntity(name) => write!(f, "Unknown entity: {}", name),
+            GoldbergError::DuplicateName { name, path, first_path } => write!(f, "{}: Name {} is already used by {}", path, name, first_path),
+            GoldbergError::Validation(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for GoldbergError {
+    fn source( &self ) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GoldbergError::Io { source, .. } => Some(source),
+            GoldbergError::Serialize { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
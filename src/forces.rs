@@ -0,0 +1,499 @@
+extern crate nalgebra as na;
+use na::{Point3, Vector3, Isometry3};
+
+use ncollide3d::shape::ShapeHandle;
+
+use nphysics3d::force_generator::ForceGenerator;
+use nphysics3d::math::{Force as Force3, ForceType};
+use nphysics3d::object::{Body, BodySet, DefaultBodyHandle};
+use nphysics3d::solver::IntegrationParameters;
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use crate::types::*;
+
+// Custom force generators, added to Simulation::force_generators when a level is loaded
+
+// Pushes dynamic bodies which are inside the zone
+pub struct ForceZone {
+    pose : Isometry3<f32>,
+    shape : ZoneShape,
+    field : ForceField,
+    falloff : Falloff,
+}
+
+impl ForceZone {
+    pub fn new( def : &ForceZoneDefinition ) -> Self {
+        ForceZone {
+            pose : Isometry3::new(Vector3::from(def.position), Vector3::from(def.rotation)),
+            shape : def.shape.clone(),
+            field : def.field.clone(),
+            falloff : def.falloff,
+        }
+    }
+
+    // How far a point is from the center towards the edge, 0 at the center and 1 on the edge
+    // None if it's outside the zone
+    fn distance( &self, point : &Point3<f32> ) -> Option<f32> {
+        let local = self.pose.inverse_transform_point(point);
+        let distance = match &self.shape {
+            ZoneShape::Box { half_extents } => (0..3)
+                .map(|i| if half_extents[i] > 0.0 { local[i].abs() / half_extents[i] } else { f32::INFINITY })
+                .fold(0.0, f32::max),
+            ZoneShape::Sphere { radius } => if *radius > 0.0 { local.coords.norm() / radius } else { f32::INFINITY },
+        };
+        if distance > 1.0 {
+            return None;
+        }
+        Some(distance)
+    }
+
+    // Acceleration at a point inside the zone
+    fn acceleration( &self, point : &Point3<f32>, distance : f32 ) -> Vector3<f32> {
+        let scale = match self.falloff {
+            Falloff::None => 1.0,
+            Falloff::Linear => 1.0 - distance,
+            Falloff::Quadratic => (1.0 - distance) * (1.0 - distance),
+        };
+        let acceleration = match &self.field {
+            ForceField::Uniform { acceleration } => self.pose.rotation * Vector3::from(*acceleration),
+            ForceField::Radial { strength } => {
+                let outwards = point - Point3::from(self.pose.translation.vector);
+                outwards.try_normalize(f32::EPSILON).unwrap_or_else(Vector3::zeros) * *strength
+            },
+        };
+        acceleration * scale
+    }
+}
+
+impl ForceGenerator<f32, DefaultBodyHandle> for ForceZone {
+    fn apply( &mut self, _parameters : &IntegrationParameters<f32>, bodies : &mut dyn BodySet<f32, Handle = DefaultBodyHandle> ) {
+        bodies.foreach_mut(&mut |_, body : &mut dyn Body<f32>| {
+            if !body.is_dynamic() {
+                return;
+            }
+            let center = match body.part(0) {
+                Some(part) => part.center_of_mass(),
+                None => return,
+            };
+            if let Some(distance) = self.distance(&center) {
+                let acceleration = self.acceleration(&center, distance);
+                body.apply_force(0, &Force3::linear(acceleration), ForceType::AccelerationChange, true);
+            }
+        });
+    }
+}
+
+// Sampled volume of each dynamic body, shared between the simulation and the generators which need it
+// Points are in the body's frame, each with the volume it stands for
+pub type BodyVolumes = Arc<RwLock<HashMap<DefaultBodyHandle, Vec<(Point3<f32>, f32)>>>>;
+
+// Spacing of the samples, in metres
+const VOLUME_CELL_SIZE : f32 = 0.1;
+// Most samples for a single shape, big shapes are sampled more coarsely
+const MAX_VOLUME_SAMPLES : usize = 1000;
+
+// Number of samples along each axis of a box, so long thin shapes are sampled along their length
+// Each axis is capped first, so huge or infinite extents can't overflow the total
+fn sample_counts( extents : &Vector3<f32> ) -> [usize; 3] {
+    let mut counts = [0, 1, 2].map(|i| (extents[i] / VOLUME_CELL_SIZE).ceil().max(1.0).min(MAX_VOLUME_SAMPLES as f32) as usize);
+    // Then make the cells bigger on every axis until the total fits
+    while counts.iter().product::<usize>() > MAX_VOLUME_SAMPLES {
+        counts = counts.map(|count| (count * 4 / 5).max(1));
+    }
+    counts
+}
+
+// Approximate a body's shapes with a grid of points, keeping those inside a shape
+pub fn volume_samples( shapes : &[ShapeHandle<f32>] ) -> Vec<(Point3<f32>, f32)> {
+    let identity = Isometry3::identity();
+    let mut samples = Vec::new();
+    for shape in shapes {
+        let query = match shape.as_point_query() {
+            Some(x) => x,
+            None => continue,
+        };
+        let aabb = shape.aabb(&identity);
+        let counts = sample_counts(&aabb.extents());
+        let cell = aabb.extents().component_div(&Vector3::new(counts[0] as f32, counts[1] as f32, counts[2] as f32));
+        let volume = cell.x * cell.y * cell.z;
+        for x in 0..counts[0] {
+            for y in 0..counts[1] {
+                for z in 0..counts[2] {
+                    let offset = Vector3::new(x as f32 + 0.5, y as f32 + 0.5, z as f32 + 0.5).component_mul(&cell);
+                    let point = aabb.mins + offset;
+                    if query.contains_point(&identity, &point) {
+                        samples.push((point, volume));
+                    }
+                }
+            }
+        }
+    }
+    samples
+}
+
+// Gravity shared with the generators which need it, the simulation keeps it in step with the world's
+pub type SharedGravity = Arc<RwLock<Vector3<f32>>>;
+
+// Buoyancy and drag for bodies inside an axis aligned box of fluid
+pub struct FluidVolume {
+    mins : Point3<f32>,
+    maxs : Point3<f32>,
+    density : f32,
+    linear_drag : f32,
+    angular_drag : f32,
+    gravity : SharedGravity,
+    volumes : BodyVolumes,
+}
+
+impl FluidVolume {
+    pub fn new( def : &FluidVolumeDefinition, gravity : SharedGravity, volumes : BodyVolumes ) -> Self {
+        let center = Point3::from(Vector3::from(def.position));
+        let half_extents = Vector3::from(def.half_extents);
+        FluidVolume {
+            mins : center - half_extents,
+            maxs : center + half_extents,
+            density : def.density,
+            linear_drag : def.linear_drag,
+            angular_drag : def.angular_drag,
+            gravity,
+            volumes,
+        }
+    }
+
+    fn contains( &self, point : &Point3<f32> ) -> bool {
+        (0..3).all(|i| point[i] >= self.mins[i] && point[i] <= self.maxs[i])
+    }
+}
+
+impl ForceGenerator<f32, DefaultBodyHandle> for FluidVolume {
+    fn apply( &mut self, parameters : &IntegrationParameters<f32>, bodies : &mut dyn BodySet<f32, Handle = DefaultBodyHandle> ) {
+        let dt = parameters.dt();
+        let volumes = match self.volumes.read() {
+            Ok(x) => x,
+            Err(_) => return,
+        };
+        let gravity = match self.gravity.read() {
+            Ok(x) => *x,
+            Err(_) => return,
+        };
+        for (handle, samples) in volumes.iter() {
+            let body = match bodies.get_mut(*handle) {
+                Some(x) => x,
+                None => continue,
+            };
+            if !body.is_dynamic() {
+                continue;
+            }
+            let (pose, velocity) = match body.part(0) {
+                Some(part) => (part.position(), part.velocity()),
+                None => continue,
+            };
+
+            // Displaced volume, and its center which the buoyancy acts through so bodies turn upright
+            let mut total = 0.0;
+            let mut submerged = 0.0;
+            let mut center = Vector3::zeros();
+            for (point, volume) in samples {
+                total += *volume;
+                let point = pose * *point;
+                if self.contains(&point) {
+                    submerged += *volume;
+                    center += point.coords * *volume;
+                }
+            }
+            if submerged <= 0.0 {
+                continue;
+            }
+            let center = Point3::from(center / submerged);
+
+            let buoyancy = -gravity * self.density * submerged;
+            body.apply_force_at_point(0, &buoyancy, &center, ForceType::Force, true);
+
+            // Drag only acts on the part that's in the fluid
+            // Decays the velocity exactly over the step, so strong drag slows bodies to a stop rather than reversing them
+            let fraction = submerged / total;
+            let decay = |drag : f32| (-drag * fraction * dt).exp() - 1.0;
+            let drag = Force3::new(
+                velocity.linear * decay(self.linear_drag),
+                velocity.angular * decay(self.angular_drag),
+            );
+            body.apply_force(0, &drag, ForceType::VelocityChange, true);
+        }
+    }
+}
+
+// A spring between two bodies, anchors are in each body's frame
+// Also kept by the simulation, so the viewer can draw it
+#[derive(Debug,Clone,Copy)]
+pub struct SpringLink {
+    pub body1 : DefaultBodyHandle,
+    pub anchor1 : Point3<f32>,
+    pub body2 : DefaultBodyHandle,
+    pub anchor2 : Point3<f32>,
+    pub rest_length : f32,
+    pub stiffness : f32,
+    pub damping : f32,
+    pub rope : bool,
+}
+
+// World position and velocity of a point fixed to a body
+fn anchor_state( bodies : &dyn BodySet<f32, Handle = DefaultBodyHandle>, handle : DefaultBodyHandle, anchor : &Point3<f32> ) -> Option<(Point3<f32>, Vector3<f32>)> {
+    let part = bodies.get(handle)?.part(0)?;
+    let point = part.position() * anchor;
+    let velocity = part.velocity();
+    Some((point, velocity.linear + velocity.angular.cross(&(point - part.center_of_mass()))))
+}
+
+pub struct Spring {
+    pub link : SpringLink,
+}
+
+impl ForceGenerator<f32, DefaultBodyHandle> for Spring {
+    fn apply( &mut self, _parameters : &IntegrationParameters<f32>, bodies : &mut dyn BodySet<f32, Handle = DefaultBodyHandle> ) {
+        let link = &self.link;
+        let (point1, velocity1) = match anchor_state(bodies, link.body1, &link.anchor1) {
+            Some(x) => x,
+            None => return,
+        };
+        let (point2, velocity2) = match anchor_state(bodies, link.body2, &link.anchor2) {
+            Some(x) => x,
+            None => return,
+        };
+
+        // From body1 towards body2
+        let delta = point2 - point1;
+        let length = delta.norm();
+        let direction = match delta.try_normalize(f32::EPSILON) {
+            Some(x) => x,
+            None => return,
+        };
+        if link.rope && length < link.rest_length {
+            return;
+        }
+        let stretch_speed = (velocity2 - velocity1).dot(&direction);
+        let mut tension = link.stiffness * (length - link.rest_length) + link.damping * stretch_speed;
+        // Ropes can't push, even while the damping is slowing them down
+        if link.rope {
+            tension = tension.max(0.0);
+        }
+
+        for (handle, point, force) in &[(link.body1, point1, direction * tension), (link.body2, point2, -direction * tension)] {
+            if let Some(body) = bodies.get_mut(*handle) {
+                if body.is_dynamic() {
+                    body.apply_force_at_point(0, force, point, ForceType::Force, true);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ncollide3d::shape::{Ball, Cuboid};
+    use nphysics3d::math::Velocity as Velocity3;
+    use nphysics3d::object::{BodyStatus, DefaultBodySet, RigidBodyDesc};
+
+    // A 2kg body, so accelerations and forces differ
+    fn add_body( bodies : &mut DefaultBodySet<f32>, position : [f32; 3], status : BodyStatus ) -> DefaultBodyHandle {
+        bodies.insert(RigidBodyDesc::new().translation(Vector3::from(position)).mass(2.0).status(status).build())
+    }
+
+    fn apply( generator : &mut dyn ForceGenerator<f32, DefaultBodyHandle>, bodies : &mut DefaultBodySet<f32> ) {
+        generator.apply(&IntegrationParameters::default(), bodies);
+    }
+
+    // Linear acceleration from the forces applied so far, without gravity
+    fn acceleration( bodies : &mut DefaultBodySet<f32>, handle : DefaultBodyHandle ) -> Vector3<f32> {
+        let body = bodies.get_mut(handle).unwrap();
+        body.update_acceleration(&Vector3::zeros(), &IntegrationParameters::default());
+        let acceleration = body.generalized_acceleration();
+        Vector3::new(acceleration[0], acceleration[1], acceleration[2])
+    }
+
+    fn assert_near( actual : Vector3<f32>, expected : [f32; 3] ) {
+        assert!((actual - Vector3::from(expected)).norm() < 1.0e-3, "{:?} isn't {:?}", actual, expected);
+    }
+
+    fn zone( shape : ZoneShape, rotation : [f32; 3], field : ForceField, falloff : Falloff ) -> ForceZone {
+        ForceZone::new(&ForceZoneDefinition { shape, position : [10.0, 0.0, 0.0], rotation, field, falloff })
+    }
+
+    #[test]
+    fn uniform_zone_pushes_dynamic_bodies_inside() {
+        // Turned a quarter round z, so up becomes -x
+        let mut zone = zone(
+            ZoneShape::Box { half_extents : [2.0, 1.0, 1.0] },
+            [0.0, 0.0, std::f32::consts::FRAC_PI_2],
+            ForceField::Uniform { acceleration : [0.0, 5.0, 0.0] },
+            Falloff::None,
+        );
+        let mut bodies = DefaultBodySet::new();
+        let inside = add_body(&mut bodies, [10.0, 1.5, 0.0], BodyStatus::Dynamic);
+        let outside = add_body(&mut bodies, [11.5, 0.0, 0.0], BodyStatus::Dynamic);
+        let fixed = add_body(&mut bodies, [10.0, 0.0, 0.0], BodyStatus::Static);
+        apply(&mut zone, &mut bodies);
+        assert_near(acceleration(&mut bodies, inside), [-5.0, 0.0, 0.0]);
+        assert_near(acceleration(&mut bodies, outside), [0.0, 0.0, 0.0]);
+        assert_near(acceleration(&mut bodies, fixed), [0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn radial_zone_fades_out() {
+        let radial = |falloff| zone(ZoneShape::Sphere { radius : 4.0 }, [0.0; 3], ForceField::Radial { strength : -8.0 }, falloff);
+        for (falloff, expected) in &[(Falloff::None, 8.0f32), (Falloff::Linear, 4.0), (Falloff::Quadratic, 2.0)] {
+            let mut bodies = DefaultBodySet::new();
+            let halfway = add_body(&mut bodies, [10.0, 0.0, 2.0], BodyStatus::Dynamic);
+            let center = add_body(&mut bodies, [10.0, 0.0, 0.0], BodyStatus::Dynamic);
+            apply(&mut radial(*falloff), &mut bodies);
+            // Negative strength pulls in
+            assert_near(acceleration(&mut bodies, halfway), [0.0, 0.0, -expected]);
+            assert_near(acceleration(&mut bodies, center), [0.0, 0.0, 0.0]);
+        }
+    }
+
+    #[test]
+    fn zones_with_no_size_do_nothing() {
+        let mut zone = zone(ZoneShape::Sphere { radius : 0.0 }, [0.0; 3], ForceField::Radial { strength : 8.0 }, Falloff::None);
+        let mut bodies = DefaultBodySet::new();
+        let body = add_body(&mut bodies, [10.0, 0.0, 0.0], BodyStatus::Dynamic);
+        apply(&mut zone, &mut bodies);
+        assert_near(acceleration(&mut bodies, body), [0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn sample_counts_stay_bounded() {
+        assert_eq!(sample_counts(&Vector3::new(0.3, 0.05, 0.0)), [3, 1, 1]);
+        assert_eq!(sample_counts(&Vector3::new(50.0, 0.1, 0.1)), [500, 1, 1]);
+        for extents in &[Vector3::new(10.0, 10.0, 10.0), Vector3::new(1.0e30, 1.0e30, 1.0e30), Vector3::repeat(f32::INFINITY), Vector3::repeat(f32::NAN)] {
+            let counts = sample_counts(extents);
+            assert!(counts.iter().all(|count| *count >= 1), "{:?}", counts);
+            assert!(counts.iter().product::<usize>() <= MAX_VOLUME_SAMPLES, "{:?}", counts);
+        }
+        // Big shapes keep their cells roughly cube shaped
+        let counts = sample_counts(&Vector3::new(10.0, 1.0, 1.0));
+        assert!(counts[0] > 5 * counts[1] && counts[1] == counts[2], "{:?}", counts);
+    }
+
+    #[test]
+    fn volume_samples_fill_the_shape() {
+        let cuboid = ShapeHandle::new(Cuboid::new(Vector3::new(0.5, 0.25, 0.5)));
+        let ball = ShapeHandle::new(Ball::new(0.5));
+        let volume = |samples : &[(Point3<f32>, f32)]| samples.iter().map(|(_, volume)| volume).sum::<f32>();
+        assert!((volume(&volume_samples(std::slice::from_ref(&cuboid))) - 0.5).abs() < 1.0e-4);
+        // 10cm samples through a 1m ball are only roughly round
+        let ball_volume = 4.0 / 3.0 * std::f32::consts::PI * 0.125;
+        let samples = volume_samples(&[ball]);
+        assert!((volume(&samples) - ball_volume).abs() < 0.1 * ball_volume, "{}", volume(&samples));
+        assert!(samples.iter().all(|(point, _)| point.coords.norm() <= 0.5));
+        // Each shape is sampled, overlaps aren't merged
+        assert!((volume(&volume_samples(&[cuboid.clone(), cuboid])) - 1.0).abs() < 1.0e-4);
+    }
+
+    // A 2kg body whose volume is two 0.5m³ samples, one above the other
+    fn floating_body( bodies : &mut DefaultBodySet<f32>, volumes : &BodyVolumes, position : [f32; 3], velocity : Velocity3<f32> ) -> DefaultBodyHandle {
+        let handle = bodies.insert(RigidBodyDesc::new().translation(Vector3::from(position)).mass(2.0).velocity(velocity).build());
+        volumes.write().unwrap().insert(handle, vec![(Point3::new(0.0, 0.25, 0.0), 0.5), (Point3::new(0.0, -0.25, 0.0), 0.5)]);
+        handle
+    }
+
+    // Water with its surface at y = 0
+    fn water( linear_drag : f32, gravity : &SharedGravity, volumes : &BodyVolumes ) -> FluidVolume {
+        let def = FluidVolumeDefinition { position : [0.0, -5.0, 0.0], half_extents : [5.0, 5.0, 5.0], density : 1000.0, linear_drag, angular_drag : 0.0 };
+        FluidVolume::new(&def, gravity.clone(), volumes.clone())
+    }
+
+    #[test]
+    fn buoyancy_follows_submerged_volume_and_gravity() {
+        let gravity = SharedGravity::new(RwLock::new(Vector3::new(0.0, -10.0, 0.0)));
+        let volumes = BodyVolumes::default();
+        let mut bodies = DefaultBodySet::new();
+        let half_under = floating_body(&mut bodies, &volumes, [0.0, 0.0, 0.0], Velocity3::zero());
+        let under = floating_body(&mut bodies, &volumes, [2.0, -2.0, 0.0], Velocity3::zero());
+        let above = floating_body(&mut bodies, &volumes, [0.0, 2.0, 0.0], Velocity3::zero());
+        let mut water = water(0.0, &gravity, &volumes);
+        apply(&mut water, &mut bodies);
+        // Displaced mass times gravity, over the body's 2kg
+        assert_near(acceleration(&mut bodies, half_under), [0.0, 2500.0, 0.0]);
+        assert_near(acceleration(&mut bodies, under), [0.0, 5000.0, 0.0]);
+        assert_near(acceleration(&mut bodies, above), [0.0, 0.0, 0.0]);
+
+        // Changing the shared gravity changes the buoyancy, without a new generator
+        *gravity.write().unwrap() = Vector3::new(0.0, -2.0, 0.0);
+        let mut bodies = DefaultBodySet::new();
+        volumes.write().unwrap().clear();
+        let under = floating_body(&mut bodies, &volumes, [2.0, -2.0, 0.0], Velocity3::zero());
+        apply(&mut water, &mut bodies);
+        assert_near(acceleration(&mut bodies, under), [0.0, 1000.0, 0.0]);
+    }
+
+    #[test]
+    fn drag_slows_bodies_without_reversing_them() {
+        let gravity = SharedGravity::new(RwLock::new(Vector3::new(0.0, -10.0, 0.0)));
+        let dt = IntegrationParameters::<f32>::default().dt();
+        for (drag, submerged_y, expected) in &[(1.0, -2.0, 10.0 * (-dt).exp()), (1.0, 0.0, 10.0 * (-0.5 * dt).exp()), (1.0e6, -2.0, 0.0)] {
+            let volumes = BodyVolumes::default();
+            let mut bodies = DefaultBodySet::new();
+            let body = floating_body(&mut bodies, &volumes, [0.0, *submerged_y, 0.0], Velocity3::linear(10.0, 0.0, 0.0));
+            apply(&mut water(*drag, &gravity, &volumes), &mut bodies);
+            let velocity = bodies.rigid_body(body).unwrap().velocity().linear;
+            assert_near(velocity, [*expected, 0.0, 0.0]);
+        }
+    }
+
+    // Two bodies 3m apart along x, joined at their centers
+    fn spring( rest_length : f32, damping : f32, rope : bool, status2 : BodyStatus, velocity2 : Velocity3<f32> ) -> (Vector3<f32>, Vector3<f32>) {
+        let mut bodies = DefaultBodySet::new();
+        let body1 = add_body(&mut bodies, [0.0, 0.0, 0.0], BodyStatus::Dynamic);
+        let body2 = bodies.insert(RigidBodyDesc::new().translation(Vector3::new(3.0, 0.0, 0.0)).mass(2.0).status(status2).velocity(velocity2).build());
+        let link = SpringLink { body1, anchor1 : Point3::origin(), body2, anchor2 : Point3::origin(), rest_length, stiffness : 10.0, damping, rope };
+        apply(&mut Spring { link }, &mut bodies);
+        (acceleration(&mut bodies, body1), acceleration(&mut bodies, body2))
+    }
+
+    #[test]
+    fn springs_pull_and_push() {
+        let still = Velocity3::zero();
+        // Stretched by 2m, 20N on each 2kg body
+        let (first, second) = spring(1.0, 0.0, false, BodyStatus::Dynamic, still);
+        assert_near(first, [10.0, 0.0, 0.0]);
+        assert_near(second, [-10.0, 0.0, 0.0]);
+        // Squashed by 2m
+        let (first, second) = spring(5.0, 0.0, false, BodyStatus::Dynamic, still);
+        assert_near(first, [-10.0, 0.0, 0.0]);
+        assert_near(second, [10.0, 0.0, 0.0]);
+        // Static bodies aren't moved, the other end still is
+        let (first, second) = spring(1.0, 0.0, false, BodyStatus::Static, still);
+        assert_near(first, [10.0, 0.0, 0.0]);
+        assert_near(second, [0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn damping_resists_stretching() {
+        // Moving apart at 1m/s adds 4N
+        let (first, _) = spring(3.0, 4.0, false, BodyStatus::Dynamic, Velocity3::linear(1.0, 0.0, 0.0));
+        assert_near(first, [2.0, 0.0, 0.0]);
+        // Rotating about its own center doesn't move the anchor
+        let (first, _) = spring(3.0, 4.0, false, BodyStatus::Dynamic, Velocity3::angular(0.0, 5.0, 0.0));
+        assert_near(first, [0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn ropes_only_pull() {
+        let still = Velocity3::zero();
+        // Slack
+        let (first, second) = spring(5.0, 0.0, true, BodyStatus::Dynamic, still);
+        assert_near(first, [0.0, 0.0, 0.0]);
+        assert_near(second, [0.0, 0.0, 0.0]);
+        // Taut
+        let (first, _) = spring(1.0, 0.0, true, BodyStatus::Dynamic, still);
+        assert_near(first, [10.0, 0.0, 0.0]);
+        // Taut, but closing fast enough that damping would push
+        let (first, _) = spring(1.0, 4.0, true, BodyStatus::Dynamic, Velocity3::linear(-10.0, 0.0, 0.0));
+        assert_near(first, [0.0, 0.0, 0.0]);
+        let (first, _) = spring(1.0, 4.0, false, BodyStatus::Dynamic, Velocity3::linear(-10.0, 0.0, 0.0));
+        assert_near(first, [-10.0, 0.0, 0.0]);
+    }
+}
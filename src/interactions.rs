@@ -3,6 +3,7 @@ extern crate kiss3d;
 extern crate nalgebra as na;
 
 use crate::engine::*;
+use crate::types::*;
 
 use na::{Point2, Vector3, Translation3, Isometry3, UnitQuaternion};
 
@@ -16,6 +17,7 @@ use kiss3d::scene::SceneNode;
 use kiss3d::planar_camera::*;
 
 use std::path::Path;
+use std::time::Instant;
 
 pub trait Interaction {
     fn on_key_down( &mut self, state : &mut AppState, k : &Key, modif : &Modifiers );
@@ -34,6 +36,7 @@ enum EditorPlacementMode {
 pub struct EditorModeInteraction {
     pub ground_collision_cuboid : Cuboid<f32>,
     pub primitive_name : String,
+    primitive_is_entity : bool, // primitive_name is an entity rather than a primitive
     cursor_ray : Ray<f32>,
     cursor_position : Point2<f32>,
     cursor_position_world : Vector3<f32>,
@@ -57,6 +60,7 @@ impl EditorModeInteraction {
             cursor_position : na::Point2::<f32>::new(0.0,0.0),
             cursor_position_world : na::Vector3::<f32>::new(0.0,0.0,0.0),
             primitive_name : String::from(primitive_name),
+            primitive_is_entity : false,
             primitive_rotation : na::Vector3::new(0.0,0.0,0.0),
             primitive_rotation_delta : 15.0_f64.to_radians() as f32,
             primitive_spawn_height : 0.5,
@@ -75,20 +79,14 @@ impl Interaction for EditorModeInteraction {
     fn on_key_down( &mut self, state : &mut AppState, k : &Key, modif : &Modifiers ) {
         match *k {
             Key::Tab => {
-                // Advance to the next primitive
-                let mut it = state.primitives_library.iter();
-                while let Some((key, _value)) = it.next() {
-                    if *key == self.primitive_name {
-                        if let Some((next_key, _next_val)) = it.next() {
-                            self.primitive_name = next_key.clone();
-                            break;
-                        } else {
-                            // Loop to beginning of primitive library
-                            if let Some((first_key, _)) = state.primitives_library.iter().next() {
-                                self.primitive_name = first_key.clone();
-                            }
-                        }
-                    }
+                // Advance to the next primitive or entity, looping back to the beginning
+                let palette = EditorModeInteraction::palette(state);
+                let next = palette.iter()
+                    .position(|(name, is_entity)| *name == self.primitive_name && *is_entity == self.primitive_is_entity)
+                    .map_or(0, |i| (i + 1) % palette.len());
+                if let Some((name, is_entity)) = palette.get(next) {
+                    self.primitive_name = name.clone();
+                    self.primitive_is_entity = *is_entity;
                 }
             },
             Key::Q => {
@@ -109,13 +107,12 @@ impl Interaction for EditorModeInteraction {
             Key::A => self.primitive_rotation.y += self.primitive_rotation_delta,
             Key::D => self.primitive_rotation.y -= self.primitive_rotation_delta,
             // Key::W => self.primitive_rotation.x -= self.primitive_rotation_delta,
-            Key::S => {
-                if modif.contains(Modifiers::Control) {
-                    save_level_definition(&state.level_definition, &state.level_file);
-                } else {
-               //     self.primitive_rotation.x += self.primitive_rotation_delta;
+            Key::S if modif.contains(Modifiers::Control) => {
+                if let Err(e) = save_level_definition(&state.level_definition, &state.level_file) {
+                    println!("ERROR: Failed to save level: {}", e);
                 }
             },
+            // Key::S => self.primitive_rotation.x += self.primitive_rotation_delta,
             Key::R => self.primitive_spawn_spacing *= 1.1,
             Key::F => self.primitive_spawn_spacing *= 0.9,
             Key::C => self.primitive_auto_rotate = !self.primitive_auto_rotate,
@@ -126,6 +123,14 @@ impl Interaction for EditorModeInteraction {
                 };
             },
             Key::Z => self.primitive_placement_static = !self.primitive_placement_static,
+            Key::Space => {
+                self.remove_preview(state);
+                state.mode = SimulationMode::Play;
+            },
+            Key::Back => {
+                self.remove_preview(state);
+                state.reset_to_level();
+            },
             _ => {}
         }
     }
@@ -139,15 +144,12 @@ impl Interaction for EditorModeInteraction {
 
         // Find the intersection between cursor ray and ground, then spawn something
         if let Some(intersection_point) = self.get_primitive_spawn_position(state) {
-            match self.primitive_placement_mode {
-                EditorPlacementMode::Singular => {
-                    if self.mouse_button1_pressed{
-                        self.primitve_last_spawn_pos = intersection_point;
-                        state.add_primitive(&self.primitive_name, &self.primitve_last_spawn_pos, &self.primitive_rotation, self.primitive_placement_static);
-                        self.mouse_button1_pressed = false;
-                    }
-                },
-                _ => {}
+            if let EditorPlacementMode::Singular = self.primitive_placement_mode {
+                if self.mouse_button1_pressed {
+                    self.primitve_last_spawn_pos = intersection_point;
+                    self.place(state);
+                    self.mouse_button1_pressed = false;
+                }
             }
         }
     }
@@ -158,7 +160,7 @@ impl Interaction for EditorModeInteraction {
     }
     fn on_mouse_move( &mut self, state : &mut AppState, x : f32, y : f32, _modif : &Modifiers ) {
         let window_size = na::Vector2::new(state.window.size()[0] as f32, state.window.size()[1] as f32);
-        let cursor_position_projected = na::Point2::new(x as f32, y as f32);
+        let cursor_position_projected = na::Point2::new(x, y);
     
         // (position, direction)
         let ray = state.camera.unproject(&cursor_position_projected, &window_size);
@@ -173,12 +175,10 @@ impl Interaction for EditorModeInteraction {
             self.cursor_position_world = intersection_point;
 
             match self.primitive_placement_mode {
-                EditorPlacementMode::Instanced => {
-                    if (intersection_point - self.primitve_last_spawn_pos).magnitude() > self.primitive_spawn_spacing {
-                        self.primitve_last_spawn_pos = intersection_point;
-                        if self.mouse_button1_pressed{
-                            state.add_primitive(&self.primitive_name, &self.primitve_last_spawn_pos, &self.primitive_rotation, self.primitive_placement_static);
-                        }
+                EditorPlacementMode::Instanced if (intersection_point - self.primitve_last_spawn_pos).magnitude() > self.primitive_spawn_spacing => {
+                    self.primitve_last_spawn_pos = intersection_point;
+                    if self.mouse_button1_pressed{
+                        self.place(state);
                     }
                 },
                 // TODO: This doesn't work, somehow prevents primitives from being spawned, though the primitive counter does increase
@@ -195,13 +195,16 @@ impl Interaction for EditorModeInteraction {
 
     fn render( &mut self, state : &mut AppState ) {
         // Disabled cause it's broken:  W/S: Rotate Primitive X
-        let control_text = format!(
+        let mut control_text = format!(
 "Controls:
     Right Mouse: Rotate Camera
     Middle Mouse: Translate Camera
-    Left drag: Spawn object: {}
+    Left drag: Spawn object: {}{}
+    Tab: Next primitive/entity
     A/D: Rotate Primitive Y
     Ctrl+S : Save level ({})
+    Space  : Play
+    Backspace : Reset to level
 
 Number of Primitives         : {},
 Auto-Rotate Active           : {},
@@ -212,6 +215,7 @@ Q/E : Primitive Spawn Height : {}
 R/F : Primitive Spacing      : {}
 C   : Primitive auto-rotate  : {}",
         self.primitive_name,
+        if self.primitive_is_entity { " (entity)" } else { "" },
         state.level_definition.name,
         state.physics_entities.len(),
         self.primitive_auto_rotate,
@@ -222,19 +226,30 @@ C   : Primitive auto-rotate  : {}",
         self.primitive_spawn_spacing,
         self.primitive_auto_rotate
         );
+        if !state.diagnostics.is_empty() || !state.level_issues.is_empty() {
+            control_text += "\n\nProblems:";
+            for d in &state.diagnostics {
+                control_text += &format!("\n{}", d);
+            }
+            for issue in &state.level_issues {
+                control_text += &format!("\n{}", issue);
+            }
+        }
         state.draw_hud_text(
             &control_text,
             &Point2::new(0.0,0.0), 30.0);
 
         // TODO: Inefficient
         // Remove any old preview
-        if let Some(x) = &mut self.render_preview {
-            state.window.remove_node(x);
-            self.render_preview = None;
-        }
+        self.remove_preview(state);
 
         // Make a new preview
-        if let Some(prim) = state.primitives_library.get_mut(&self.primitive_name) {
+        let preview = if self.primitive_is_entity {
+            state.sim.entities_library.get(&self.primitive_name).map(|ent| &ent.primitive)
+        } else {
+            state.sim.primitives_library.get(&self.primitive_name)
+        };
+        if let Some(prim) = preview {
             let prim_scale = Vector3::from(prim.scale);
             let mut gfx = state.window.add_obj(
                 Path::new(&format!("{}/{}", state.assets_path, prim.path_obj)),
@@ -266,6 +281,32 @@ C   : Primitive auto-rotate  : {}",
     }
 }
 impl EditorModeInteraction {
+    // Everything which can be placed, primitives then entities (name, is entity)
+    fn palette( state : &AppState ) -> Vec<(String, bool)> {
+        state.sim.primitives_library.keys().map(|name| (name.clone(), false))
+            .chain(state.sim.entities_library.keys().map(|name| (name.clone(), true)))
+            .collect()
+    }
+
+    // Add the selected primitive or entity at the last spawn position
+    fn place( &self, state : &mut AppState ) {
+        let result = if self.primitive_is_entity {
+            state.add_entity(&self.primitive_name, &self.primitve_last_spawn_pos, &self.primitive_rotation, self.primitive_placement_static)
+        } else {
+            state.add_primitive(&self.primitive_name, &self.primitve_last_spawn_pos, &self.primitive_rotation, self.primitive_placement_static)
+        };
+        if let Err(e) = result {
+            println!("ERROR: Failed to add {}: {}", self.primitive_name, e);
+        }
+    }
+
+    fn remove_preview(&mut self, state : &mut AppState) {
+        if let Some(x) = &mut self.render_preview {
+            state.window.remove_node(x);
+            self.render_preview = None;
+        }
+    }
+
     fn get_primitive_spawn_position(&mut self, state : &mut AppState) -> Option<Vector3<f32>> {
         // Find the intersection between cursor ray and an object, then spawn something
         // Ideally the user clicked on the top of an object, but if it's the side we'll spawn anyway
@@ -274,7 +315,7 @@ impl EditorModeInteraction {
         let mut toi = 10000.0;
         if intersect_all {
             let groups = CollisionGroups::new();
-            let ray_interferences = state.geometrical_world.interferences_with_ray(&state.colliders, 
+            let ray_interferences = state.sim.geometrical_world.interferences_with_ray(&state.sim.colliders, 
                 &self.cursor_ray, 10000.0, &groups);
             
             for (_, b, inter) in ray_interferences {
@@ -321,3 +362,197 @@ impl EditorModeInteraction {
         None
     }
 }
+
+// Simulation running, no editing
+#[derive(Default)]
+pub struct PlayModeInteraction {
+}
+impl PlayModeInteraction {
+    pub fn new() -> Self {
+        PlayModeInteraction {
+        }
+    }
+}
+
+impl Interaction for PlayModeInteraction {
+    fn on_key_down( &mut self, state : &mut AppState, k : &Key, _modif : &Modifiers ) {
+        match *k {
+            // Stopping always goes back to the level as defined, edit mode doesn't have moved bodies
+            Key::Space | Key::Back => state.reset_to_level(),
+            Key::F5 => {
+                let snapshot = state.sim.snapshot(&state.level_definition.name);
+                if let Err(e) = save_snapshot(&snapshot, &snapshot_file_for_level(&state.level_file)) {
+                    println!("ERROR: Failed to save snapshot: {}", e);
+                }
+            },
+            Key::F9 => {
+                let result = load_snapshot(&snapshot_file_for_level(&state.level_file))
+                    .and_then(|snapshot| state.sim.restore_snapshot(&snapshot));
+                if let Err(e) = result {
+                    println!("ERROR: Failed to restore snapshot: {}", e);
+                }
+                // Anything spawned has been replaced
+                state.rebuild_physics_entities();
+            },
+            Key::M => {
+                let enabled = !state.sim.motors.iter().any(|m| m.enabled);
+                for joint in 0..state.sim.joints.len() {
+                    state.sim.set_motor_enabled(joint, enabled);
+                }
+            },
+            // Switch everything off if anything is on, otherwise switch everything on
+            Key::E => {
+                let active = !state.sim.entities.iter().any(|ent| ent.active);
+                state.sim.set_entities_active(active);
+            },
+            _ => {}
+        }
+    }
+    fn on_key_up( &mut self, _state : &mut AppState, _k : &Key, _modif : &Modifiers ) {
+
+    }
+    fn on_mouse_down( &mut self, _state : &mut AppState, _k : &MouseButton, _modif : &Modifiers ) {
+
+    }
+    fn on_mouse_up( &mut self, _state : &mut AppState, _k : &MouseButton, _modif : &Modifiers ) {
+
+    }
+    fn on_mouse_move( &mut self, _state : &mut AppState, _x : f32, _y : f32, _modif : &Modifiers ) {
+
+    }
+
+    fn render( &mut self, state : &mut AppState ) {
+        let control_text = format!(
+"Playing: {}
+    Right Mouse: Rotate Camera
+    Middle Mouse: Translate Camera
+    Space/Backspace : Stop and reset to level
+    F5/F9  : Save/Restore snapshot
+    E      : Toggle entities on/off
+    M      : Toggle motors on/off
+
+Number of Primitives         : {},
+Active Entities              : {}/{},
+Time                         : {:.2}s",
+        state.level_definition.name,
+        state.physics_entities.len(),
+        state.sim.entities.iter().filter(|ent| ent.active).count(),
+        state.sim.entities.len(),
+        state.sim.time(),
+        );
+        state.draw_hud_text(
+            &control_text,
+            &Point2::new(0.0,0.0), 30.0);
+    }
+}
+
+// Plays back a recorded trajectory, physics isn't stepped
+pub struct ReplayInteraction {
+    pub trajectory : Trajectory,
+    // Nodes for bodies spawned during the recording, after the level's own. None if it couldn't be loaded
+    spawned : Vec<Option<SceneNode>>,
+    time : f32,
+    speed : f32,
+    paused : bool,
+    last_render : Instant,
+}
+impl ReplayInteraction {
+    pub fn new(trajectory : Trajectory) -> Self {
+        let time = trajectory.start_time();
+        ReplayInteraction {
+            trajectory,
+            spawned : Vec::new(),
+            time,
+            speed : 1.0,
+            paused : false,
+            last_render : Instant::now(),
+        }
+    }
+
+    fn scrub( &mut self, delta : f32 ) {
+        self.time = (self.time + delta).max(self.trajectory.start_time()).min(self.trajectory.end_time());
+    }
+}
+
+impl Interaction for ReplayInteraction {
+    fn on_key_down( &mut self, _state : &mut AppState, k : &Key, modif : &Modifiers ) {
+        // Shift for fine scrubbing
+        let scrub_step = if modif.contains(Modifiers::Shift) { 0.1 } else { 1.0 };
+        match *k {
+            Key::Space => self.paused = !self.paused,
+            Key::Left => self.scrub(-scrub_step),
+            Key::Right => self.scrub(scrub_step),
+            Key::Up => self.speed *= 2.0,
+            Key::Down => self.speed *= 0.5,
+            Key::Home => self.time = self.trajectory.start_time(),
+            _ => {}
+        }
+    }
+    fn on_key_up( &mut self, _state : &mut AppState, _k : &Key, _modif : &Modifiers ) {
+
+    }
+    fn on_mouse_down( &mut self, _state : &mut AppState, _k : &MouseButton, _modif : &Modifiers ) {
+
+    }
+    fn on_mouse_up( &mut self, _state : &mut AppState, _k : &MouseButton, _modif : &Modifiers ) {
+
+    }
+    fn on_mouse_move( &mut self, _state : &mut AppState, _x : f32, _y : f32, _modif : &Modifiers ) {
+
+    }
+
+    fn render( &mut self, state : &mut AppState ) {
+        let frame_delta = self.last_render.elapsed().as_secs_f32();
+        self.last_render = Instant::now();
+        if !self.paused {
+            self.scrub(frame_delta * self.speed);
+        }
+
+        for (i, ent) in state.physics_entities.iter_mut().enumerate() {
+            if let Some(pos) = self.trajectory.pose_at(i, self.time) {
+                ent.node.set_local_transformation(pos);
+            }
+        }
+
+        // Bodies spawned during the recording, hidden until they were spawned
+        let level_bodies = state.physics_entities.len();
+        while level_bodies + self.spawned.len() < self.trajectory.bodies.len() {
+            let i = level_bodies + self.spawned.len();
+            let scale = self.trajectory.scales.get(i).map_or_else(|| Vector3::repeat(1.0), |s| Vector3::from(*s));
+            let node = state.add_primitive_node(&self.trajectory.bodies[i], scale, None);
+            self.spawned.push(node);
+        }
+        for (i, node) in self.spawned.iter_mut().enumerate() {
+            if let Some(node) = node {
+                match self.trajectory.pose_at(level_bodies + i, self.time) {
+                    Some(pos) => {
+                        node.set_visible(true);
+                        node.set_local_transformation(pos);
+                    },
+                    None => node.set_visible(false),
+                }
+            }
+        }
+
+        let springs = state.sim.spring_endpoints_at(|i| self.trajectory.pose_at(i, self.time));
+        state.draw_springs(&springs);
+
+        let control_text = format!(
+"Replay: {}
+    Space      : Pause ({})
+    Left/Right : Scrub (Shift for fine)
+    Up/Down    : Playback speed ({}x)
+    Home       : Restart
+
+Time: {:.2}s / {:.2}s",
+        self.trajectory.level,
+        self.paused,
+        self.speed,
+        self.time,
+        self.trajectory.end_time(),
+        );
+        state.draw_hud_text(
+            &control_text,
+            &Point2::new(0.0,0.0), 30.0);
+    }
+}
@@ -2,6 +2,12 @@
 pub mod types;
 pub mod interactions;
 pub mod engine;
+pub mod error;
+pub mod migration;
+pub mod obj;
+pub mod validation;
+pub mod decomposition;
+pub mod forces;
 
 // include!("main.rs");
 
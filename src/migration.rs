@@ -0,0 +1,277 @@
+use serde_json::Value;
+
+use crate::error::GoldbergError;
+
+// Current format version of each kind of file
+// Bump these and add a migration below whenever the matching types change
+pub const LEVEL_FORMAT_VERSION : u32 = 1;
+pub const PRIMITIVE_FORMAT_VERSION : u32 = 1;
+pub const ENTITY_FORMAT_VERSION : u32 = 3;
+
+// Upgrades a file's json by one version, returns a description of the problem if it can't
+type Migration = fn( &mut Value ) -> Result<(), String>;
+
+// Migration n upgrades from format version n to n + 1
+const LEVEL_MIGRATIONS : &[Migration] = &[
+    unversioned_to_v1,
+];
+const PRIMITIVE_MIGRATIONS : &[Migration] = &[
+    unversioned_to_v1,
+];
+const ENTITY_MIGRATIONS : &[Migration] = &[
+    unversioned_to_v1,
+    entity_v1_to_v2,
+    entity_v2_to_v3,
+];
+
+#[derive(Debug,Clone,Copy)]
+pub enum FormatKind {
+    Level,
+    Primitive,
+    Entity,
+}
+impl FormatKind {
+    pub fn current_version( self ) -> u32 {
+        match self {
+            FormatKind::Level => LEVEL_FORMAT_VERSION,
+            FormatKind::Primitive => PRIMITIVE_FORMAT_VERSION,
+            FormatKind::Entity => ENTITY_FORMAT_VERSION,
+        }
+    }
+
+    fn migrations( self ) -> &'static [Migration] {
+        match self {
+            FormatKind::Level => LEVEL_MIGRATIONS,
+            FormatKind::Primitive => PRIMITIVE_MIGRATIONS,
+            FormatKind::Entity => ENTITY_MIGRATIONS,
+        }
+    }
+}
+
+// Upgrade a file's json to the current format version
+// Files from before format_version existed are version 0
+// Returns true if anything was upgraded
+pub fn migrate( value : &mut Value, kind : FormatKind, path : &str ) -> Result<bool, GoldbergError> {
+    if !value.is_object() {
+        return Err(GoldbergError::Validation(format!("{}: Expected a json object", path)));
+    }
+
+    let mut version = match value.get("format_version") {
+        None => 0,
+        Some(x) => match x.as_u64() {
+            Some(v) => v as u32,
+            None => return Err(GoldbergError::Validation(format!("{}: format_version must be a number", path))),
+        },
+    };
+    let current = kind.current_version();
+    if version > current {
+        return Err(GoldbergError::Validation(format!(
+            "{}: Format version {} is newer than this version of goldberg supports ({})", path, version, current)));
+    }
+
+    let mut upgraded = version < current;
+    while version < current {
+        let migration = kind.migrations()[version as usize];
+        migration(value).map_err(|e| GoldbergError::Validation(format!(
+            "{}: Failed to upgrade from format version {}: {}", path, version, e)))?;
+        version += 1;
+        value["format_version"] = Value::from(version);
+    }
+
+    // Entities carry a whole primitive, keep it up to date too
+    if let FormatKind::Entity = kind {
+        if let Some(prim) = value.get_mut("primitive") {
+            upgraded |= migrate(prim, FormatKind::Primitive, path)?;
+        }
+    }
+
+    Ok(upgraded)
+}
+
+// Files from before format_version was added, nothing else changed
+fn unversioned_to_v1( _value : &mut Value ) -> Result<(), String> {
+    Ok(())
+}
+
+// Entity parameters moved from flat cannon_* fields into the entity_type
+fn entity_v1_to_v2( value : &mut Value ) -> Result<(), String> {
+    let ent = value.as_object_mut().ok_or("Expected a json object")?;
+    let entity_type = match ent.get("entity_type") {
+        Some(Value::String(x)) => x.clone(),
+        _ => return Err(String::from("entity_type must be a string")),
+    };
+
+    let mut params = serde_json::Map::new();
+    params.insert(String::from("type"), Value::from(entity_type.as_str()));
+    match entity_type.as_str() {
+        "Cannon" => {
+            for (old, new) in &[
+                ("cannon_spawn_point", "spawn_point"),
+                ("cannon_spawn_force", "spawn_force"),
+                ("cannon_projectile_name", "projectile"),
+                ("cannon_projectile_scale", "projectile_scale"),
+                ("cannon_ammo", "ammo"),
+                ("cannon_fire_delay", "fire_delay"),
+            ] {
+                let param = ent.remove(*old).ok_or_else(|| format!("Missing {}", old))?;
+                params.insert(String::from(*new), param);
+            }
+        },
+        x => return Err(format!("Unknown entity_type: {}", x)),
+    }
+    ent.insert(String::from("entity_type"), Value::Object(params));
+    Ok(())
+}
+
+// Cannons launch with a velocity rather than an impulse, so spawn_force became spawn_velocity
+// An impulse can't be turned into a velocity without the projectile's mass, so only zero is carried over
+fn entity_v2_to_v3( value : &mut Value ) -> Result<(), String> {
+    let params = match value.get_mut("entity_type") {
+        Some(Value::Object(x)) => x,
+        _ => return Err(String::from("entity_type must be an object")),
+    };
+    if params.get("type") != Some(&Value::from("Cannon")) {
+        return Ok(());
+    }
+    let force = params.remove("spawn_force").ok_or("Missing spawn_force")?;
+    let is_zero = force.as_array().is_some_and(|x| x.iter().all(|f| f.as_f64() == Some(0.0)));
+    if !is_zero {
+        return Err(String::from("spawn_force was an impulse, replace it with spawn_velocity, the projectile's launch velocity in m/s"));
+    }
+    params.insert(String::from("spawn_velocity"), force);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::de::DeserializeOwned;
+    use serde_json::json;
+    use std::fs;
+    use crate::types::*;
+
+    // Migrate and deserialise every .json file shipped in an assets directory
+    fn load_shipped<T : DeserializeOwned>( dir : &str, kind : FormatKind ) -> Vec<(String, T)> {
+        let mut loaded = Vec::new();
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|x| x != "json") {
+                continue;
+            }
+            let path = path.to_string_lossy().to_string();
+            let mut value : Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+            migrate(&mut value, kind, &path).unwrap_or_else(|e| panic!("{}", e));
+            assert_eq!(value["format_version"], json!(kind.current_version()), "{}", path);
+            let result = serde_json::from_value(value).unwrap_or_else(|e| panic!("{}: {}", path, e));
+            loaded.push((path, result));
+        }
+        loaded
+    }
+
+    #[test]
+    fn shipped_levels_load() {
+        let levels : Vec<(String, LevelDefinition)> = load_shipped("assets/levels", FormatKind::Level);
+        for name in &["default.json", "dominos_olc.json"] {
+            assert!(levels.iter().any(|(path, _)| path.ends_with(name)), "Missing {}", name);
+        }
+    }
+
+    #[test]
+    fn shipped_entities_load() {
+        let entities : Vec<(String, EntityDefinition)> = load_shipped("assets/entities", FormatKind::Entity);
+        assert!(!entities.is_empty());
+        for (path, ent) in &entities {
+            assert_eq!(ent.primitive.format_version, PRIMITIVE_FORMAT_VERSION, "{}", path);
+        }
+    }
+
+    #[test]
+    fn shipped_primitives_load() {
+        let prims : Vec<(String, PrimitiveDefinition)> = load_shipped("assets/primitives", FormatKind::Primitive);
+        assert!(!prims.is_empty());
+        let hidden : Vec<(String, PrimitiveDefinition)> = load_shipped("assets/primitives_hidden", FormatKind::Primitive);
+        assert!(!hidden.is_empty());
+    }
+
+    #[test]
+    fn cannon_fields_move_into_entity_type() {
+        let mut value = json!({
+            "name" : "old-cannon",
+            "entity_type" : "Cannon",
+            "cannon_spawn_point" : [0.0, 1.0, 2.0],
+            "cannon_spawn_force" : [0.0, 0.0, 0.0],
+            "cannon_projectile_name" : "cannon-ball-1m",
+            "cannon_projectile_scale" : [1.0, 1.0, 1.0],
+            "cannon_ammo" : 3,
+            "cannon_fire_delay" : 0.5,
+            "primitive" : { "name" : "old-cannon" },
+        });
+        assert!(migrate(&mut value, FormatKind::Entity, "old-cannon.json").unwrap());
+        assert_eq!(value["format_version"], json!(ENTITY_FORMAT_VERSION));
+        assert_eq!(value["primitive"]["format_version"], json!(PRIMITIVE_FORMAT_VERSION));
+        assert_eq!(value["entity_type"], json!({
+            "type" : "Cannon",
+            "spawn_point" : [0.0, 1.0, 2.0],
+            "spawn_velocity" : [0.0, 0.0, 0.0],
+            "projectile" : "cannon-ball-1m",
+            "projectile_scale" : [1.0, 1.0, 1.0],
+            "ammo" : 3,
+            "fire_delay" : 0.5,
+        }));
+        assert!(value.get("cannon_ammo").is_none());
+    }
+
+    #[test]
+    fn cannon_impulse_isnt_taken_as_a_velocity() {
+        let cannon = |force| json!({
+            "format_version" : 2,
+            "name" : "cannon",
+            "entity_type" : {
+                "type" : "Cannon",
+                "spawn_point" : [0.0, 1.0, 2.0],
+                "spawn_force" : force,
+                "projectile" : "cannon-ball-1m",
+                "ammo" : 3,
+                "fire_delay" : 0.5,
+            },
+            "primitive" : { "format_version" : 1, "name" : "cannon" },
+        });
+        let mut value = cannon(json!([0.0, 0.0, 1000.0]));
+        match migrate(&mut value, FormatKind::Entity, "cannon.json") {
+            Err(GoldbergError::Validation(message)) => assert!(message.contains("spawn_velocity"), "{}", message),
+            _ => panic!("Expected the impulse to be rejected"),
+        }
+
+        let mut value = cannon(json!([0.0, 0.0, 0.0]));
+        assert!(migrate(&mut value, FormatKind::Entity, "cannon.json").unwrap());
+        assert_eq!(value["entity_type"]["spawn_velocity"], json!([0.0, 0.0, 0.0]));
+        assert!(value["entity_type"].get("spawn_force").is_none());
+
+        let mut switch = json!({
+            "format_version" : 2,
+            "name" : "switch",
+            "entity_type" : { "type" : "MotorSwitch", "joint" : null },
+            "primitive" : { "format_version" : 1, "name" : "switch" },
+        });
+        assert!(migrate(&mut switch, FormatKind::Entity, "switch.json").unwrap());
+        assert_eq!(switch["entity_type"], json!({ "type" : "MotorSwitch", "joint" : null }));
+    }
+
+    #[test]
+    fn current_files_are_left_alone() {
+        let mut value = json!({ "format_version" : LEVEL_FORMAT_VERSION, "name" : "x" });
+        let before = value.clone();
+        assert!(!migrate(&mut value, FormatKind::Level, "x.json").unwrap());
+        assert_eq!(value, before);
+    }
+
+    #[test]
+    fn newer_and_broken_files_are_rejected() {
+        let mut newer = json!({ "format_version" : LEVEL_FORMAT_VERSION + 1 });
+        assert!(migrate(&mut newer, FormatKind::Level, "x.json").is_err());
+        let mut not_object = json!([1, 2, 3]);
+        assert!(migrate(&mut not_object, FormatKind::Level, "x.json").is_err());
+        let mut unknown = json!({ "format_version" : 1, "entity_type" : "Trebuchet", "primitive" : {} });
+        assert!(migrate(&mut unknown, FormatKind::Entity, "x.json").is_err());
+    }
+}
@@ -0,0 +1,205 @@
+extern crate nalgebra as na;
+use na::{Point3, Vector3};
+
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufReader;
+
+use ncollide3d::shape::ConvexHull;
+
+use crate::error::GoldbergError;
+use crate::types::*;
+
+// Just the geometry from a .obj file, materials/normals/uvs are ignored
+// Coordinates are as exported (y up), so match the engine's axes
+pub struct ObjMesh {
+    pub vertices : Vec<Point3<f32>>,
+    pub triangles : Vec<[usize; 3]>, // Indices into vertices, polygons are split into fans
+}
+
+impl ObjMesh {
+    // Axis aligned bounds (min, max), None if there aren't any vertices
+    pub fn bounds( &self ) -> Option<(Point3<f32>, Point3<f32>)> {
+        let first = self.vertices.first()?;
+        let mut min = *first;
+        let mut max = *first;
+        for v in &self.vertices {
+            min.coords = min.coords.inf(&v.coords);
+            max.coords = max.coords.sup(&v.coords);
+        }
+        Some((min, max))
+    }
+
+    // Smallest sphere centred on the bounds which contains every vertex (center, radius)
+    pub fn bounding_sphere( &self ) -> Option<(Point3<f32>, f32)> {
+        let (min, max) = self.bounds()?;
+        let center = na::center(&min, &max);
+        let radius = self.vertices.iter().map(|v| na::distance(&center, v)).fold(0.0, f32::max);
+        Some((center, radius))
+    }
+
+    // Copy of the mesh with each axis scaled
+    pub fn scaled( &self, scale : &Vector3<f32> ) -> ObjMesh {
+        ObjMesh {
+            vertices : self.vertices.iter().map(|v| Point3::new(v.x * scale.x, v.y * scale.y, v.z * scale.z)).collect(),
+            triangles : self.triangles.clone(),
+        }
+    }
+}
+
+pub fn load_obj( obj_file : &str ) -> Result<ObjMesh, GoldbergError> {
+    let file = File::open(obj_file).map_err(|e| GoldbergError::io(obj_file, e))?;
+    parse_obj(BufReader::new(file), obj_file)
+}
+
+// Read the geometry out of .obj text, obj_file is just for errors
+fn parse_obj<R : BufRead>( reader : R, obj_file : &str ) -> Result<ObjMesh, GoldbergError> {
+    let mut mesh = ObjMesh {
+        vertices : Vec::new(),
+        triangles : Vec::new(),
+    };
+    for (line_index, line) in reader.lines().enumerate() {
+        let line = line.map_err(|e| GoldbergError::io(obj_file, e))?;
+        let parse_error = |message : &str| GoldbergError::Parse {
+            path : String::from(obj_file),
+            line : line_index + 1,
+            column : 0,
+            message : format!("{} (line {})", message, line_index + 1),
+        };
+
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("v") => {
+                let mut coords = [0.0; 3];
+                for c in &mut coords {
+                    *c = tokens.next()
+                        .and_then(|t| t.parse().ok())
+                        .ok_or_else(|| parse_error("Invalid vertex"))?;
+                }
+                mesh.vertices.push(Point3::new(coords[0], coords[1], coords[2]));
+            },
+            Some("f") => {
+                // Each token is v, v/vt, v//vn or v/vt/vn, indices are 1 based or negative from the end
+                let mut indices = Vec::new();
+                for t in tokens {
+                    let index : i64 = t.split('/').next()
+                        .and_then(|i| i.parse().ok())
+                        .ok_or_else(|| parse_error("Invalid face"))?;
+                    let index = if index < 0 { mesh.vertices.len() as i64 + index } else { index - 1 };
+                    if index < 0 || index >= mesh.vertices.len() as i64 {
+                        return Err(parse_error("Face references a missing vertex"));
+                    }
+                    indices.push(index as usize);
+                }
+                for i in 2..indices.len() {
+                    mesh.triangles.push([indices[0], indices[i - 1], indices[i]]);
+                }
+            },
+            _ => {}
+        }
+    }
+    Ok(mesh)
+}
+
+// Generated colliders, these fit the mesh as exported so the primitive's scale is still applied on top
+
+// Cuboid matching the mesh's bounds
+pub fn fit_cuboid_collider( mesh : &ObjMesh ) -> Option<ColliderDefinition> {
+    let (min, max) = mesh.bounds()?;
+    let center = na::center(&min, &max);
+    let half = (max - min) / 2.0;
+    Some(ColliderDefinition {
+        collider_type : ColliderType::Cuboid,
+        origin : [center.x, center.y, center.z],
+        rotation : [0.0; 3],
+        dimensions : [half.x, half.y, half.z],
+        points : Vec::new(),
+        triangles : Vec::new(),
+    })
+}
+
+// Ball containing every vertex
+pub fn fit_ball_collider( mesh : &ObjMesh ) -> Option<ColliderDefinition> {
+    let (center, radius) = mesh.bounding_sphere()?;
+    Some(ColliderDefinition {
+        collider_type : ColliderType::Ball,
+        origin : [center.x, center.y, center.z],
+        rotation : [0.0; 3],
+        dimensions : [radius, radius, radius],
+        points : Vec::new(),
+        triangles : Vec::new(),
+    })
+}
+
+// Convex hull of the mesh, only the points on the hull are kept
+pub fn fit_convex_hull_collider( mesh : &ObjMesh ) -> Option<ColliderDefinition> {
+    let hull = ConvexHull::try_from_points(&mesh.vertices)?;
+    Some(ColliderDefinition {
+        collider_type : ColliderType::ConvexHull,
+        origin : [0.0; 3],
+        rotation : [0.0; 3],
+        dimensions : [0.0; 3],
+        points : hull.points().iter().map(|p| [p.x, p.y, p.z]).collect(),
+        triangles : Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse( text : &str ) -> Result<ObjMesh, GoldbergError> {
+        parse_obj(text.as_bytes(), "test.obj")
+    }
+
+    #[test]
+    fn faces_are_split_into_fans() {
+        let mesh = parse("o quad\nv 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvn 0 0 1\nf 1//1 2//1 3//1 4//1\n").unwrap();
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.triangles, vec![[0, 1, 2], [0, 2, 3]]);
+    }
+
+    #[test]
+    fn negative_indices_count_from_the_end() {
+        let mesh = parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nf -3/1 -2/2 -1/3\n").unwrap();
+        assert_eq!(mesh.triangles, vec![[0, 1, 2]]);
+    }
+
+    #[test]
+    fn bad_lines_report_where_they_are() {
+        match parse("v 0 0 0\nv 1 0\n") {
+            Err(GoldbergError::Parse { line, .. }) => assert_eq!(line, 2),
+            _ => panic!("Expected a parse error"),
+        }
+        match parse("v 0 0 0\nv 1 0 0\nf 1 2 3\n") {
+            Err(GoldbergError::Parse { line, .. }) => assert_eq!(line, 3),
+            _ => panic!("Expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn colliders_fit_the_mesh() {
+        let mesh = parse("v -1 0 -2\nv 1 2 2\nv 1 0 -2\n").unwrap();
+        let cuboid = fit_cuboid_collider(&mesh).unwrap();
+        assert_eq!(cuboid.origin, [0.0, 1.0, 0.0]);
+        assert_eq!(cuboid.dimensions, [1.0, 1.0, 2.0]);
+        let ball = fit_ball_collider(&mesh).unwrap();
+        assert!((ball.dimensions[0] - 6.0f32.sqrt()).abs() < 1.0e-5);
+        assert!(fit_cuboid_collider(&parse("").unwrap()).is_none());
+    }
+
+    #[test]
+    fn shipped_models_load() {
+        for obj_file in &[
+            "assets/models/ball/ball.obj",
+            "assets/models/cannon/cannon.obj",
+            "assets/models/cannonball-1m/cannonball-1m.obj",
+            "assets/models/cubey/cube.obj",
+            "assets/models/domino/domino.obj",
+            "assets/models/steps/steps.obj",
+        ] {
+            let mesh = load_obj(obj_file).unwrap();
+            assert!(!mesh.triangles.is_empty(), "{} has no faces", obj_file);
+        }
+    }
+}
@@ -1,15 +1,23 @@
 
 use serde_derive::*;
+use serde_json::{Map, Value};
 
 // Primitive serialisation
 #[derive(Serialize,Deserialize,Debug)]
 pub enum ColliderType {
     Cuboid,
     Ball,
+    Capsule,
+    Cylinder,
+    Cone,
+    ConvexHull,
+    TriMesh, // Static bodies only
 }
 
 #[derive(Serialize,Deserialize,Debug)]
 pub struct PrimitiveDefinition {
+    #[serde(default)]
+    pub format_version : u32, // See migration.rs, 0 if from before versioning
     pub name     : String,
     pub path_obj : String, // Path to the .obj file
     pub path_mtl : String, // Path to the directory containing the .mtl files
@@ -26,20 +34,82 @@ pub struct PrimitiveDefinition {
 pub struct ColliderDefinition {
     pub collider_type : ColliderType,
     pub origin : [f32; 3], // Center of the collider, relative to the primitive
+    #[serde(default)]
+    pub rotation : [f32; 3], // Euler angles in degrees (x, y, z), about the collider's origin
     // Dimensions of the collider. Meaning varies based on collider type
     // Cuboid - half x,y,z
     // Sphere - half radius,_,_
+    // Capsule/Cylinder/Cone - radius,half height,_ (aligned with y, cone points up)
+    // ConvexHull/TriMesh - Not used, see points
     pub dimensions : [f32; 3],
+    // ConvexHull - Points to wrap, TriMesh - Mesh vertices. Relative to origin
+    // If empty the geometry is taken from the primitive's .obj when the library is loaded
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub points : Vec<[f32; 3]>,
+    // TriMesh only - Indices into points
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub triangles : Vec<[usize; 3]>,
 }
 
 // Scene/level serialisation
 #[derive(Serialize,Deserialize,Debug)]
 pub struct LevelDefinition {
+    #[serde(default)]
+    pub format_version : u32, // See migration.rs, 0 if from before versioning
     pub name : String,
     pub ground_dimensions : [f32; 2], // Width/Depth
     pub ground_colour : [f32; 3], // rgb, 0 -> 1
     pub background_colour : [f32; 3], // rgb, 0 -> 1
+    #[serde(default)]
+    pub world : WorldSettings,
     pub primitives : Vec<LevelPrimitiveDefinition>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub entities : Vec<LevelEntityDefinition>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub joints : Vec<LevelJointDefinition>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub force_zones : Vec<ForceZoneDefinition>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fluids : Vec<FluidVolumeDefinition>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub springs : Vec<LevelSpringDefinition>,
+}
+
+pub const DEFAULT_TIMESTEP : f32 = 1.0 / 60.0;
+pub const DEFAULT_MAX_SUBSTEPS : u32 = 8;
+
+// Environment the level runs in, defaults match levels from before these were configurable
+#[derive(Serialize,Deserialize,Debug,Clone)]
+#[serde(default)]
+pub struct WorldSettings {
+    pub gravity : [f32; 3], // m/s^2
+    pub light_position : [f32; 3],
+    pub light_colour : [f32; 3], // rgb, 0 -> 1
+    pub camera_eye : [f32; 3], // Initial camera position
+    pub camera_target : [f32; 3], // Initial point the camera looks at
+    pub solver : Option<SolverSettings>, // If not set the nphysics defaults are used
+    pub timestep : f32, // Size of each physics step, in seconds
+    pub max_substeps : u32, // Most steps taken in one frame, if a frame takes longer the simulation slows down
+}
+impl Default for WorldSettings {
+    fn default() -> Self {
+        WorldSettings {
+            gravity : [0.0, -9.81, 0.0],
+            light_position : [0.0, 50.0, 0.0],
+            light_colour : [1.0, 1.0, 1.0],
+            camera_eye : [-50.0, 50.0, -50.0],
+            camera_target : [0.0, 1.5, 0.0],
+            solver : None,
+            timestep : DEFAULT_TIMESTEP,
+            max_substeps : DEFAULT_MAX_SUBSTEPS,
+        }
+    }
+}
+
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct SolverSettings {
+    pub max_velocity_iterations : usize,
+    pub max_position_iterations : usize,
 }
 #[derive(Serialize,Deserialize,Debug,Clone)]
 pub struct LevelPrimitiveDefinition {
@@ -47,25 +117,253 @@ pub struct LevelPrimitiveDefinition {
     pub position : [f32; 3],
     pub rotation : [f32; 3],
     pub is_static: bool, // If true the body is static, false dynamic
+    // Per-instance overrides, if not set the value from the PrimitiveDefinition is used
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scale : Option<[f32; 3]>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub density : Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub friction : Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restitution : Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub colour : Option<[f32; 3]>, // rgb tint, 0 -> 1
+    // Initial velocities, at rest if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub linear_velocity : Option<[f32; 3]>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub angular_velocity : Option<[f32; 3]>,
+}
+
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct LevelEntityDefinition {
+    pub name : String, // Name of an EntityDefinition
+    pub position : [f32; 3],
+    pub rotation : [f32; 3],
+    #[serde(default)]
+    pub is_static: bool,
+    // Per-instance overrides, if not set the value from the EntityDefinition is used
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active_default : Option<bool>,
+    // Any of the fields of the entity's entity_type, e.g. { "ammo" : 5, "fire_delay" : 2.0 }
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub parameters : Map<String, Value>,
+}
+
+// Joints connect two placed primitives, or a primitive and the world
+// Anchors and axes are in world coordinates, as the level is placed
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct LevelJointDefinition {
+    pub joint_type : JointType,
+    pub body1 : usize, // Index into primitives
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body2 : Option<usize>, // Index into primitives, attached to the world if not set
+    pub anchor : [f32; 3], // Point the bodies are connected at
+}
+
+// Tagged with "type" in json, e.g. "joint_type": { "type": "Revolute", "axis": [0.0, 0.0, 1.0] }
+#[derive(Serialize,Deserialize,Debug,Clone)]
+#[serde(tag = "type")]
+pub enum JointType {
+    // Hinge, rotates about axis. Limits are min/max angle in degrees, 0 as placed
+    Revolute {
+        axis : [f32; 3],
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        limits : Option<[f32; 2]>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        motor : Option<MotorDefinition>, // Velocity in rad/s, force is a torque in N m
+    },
+    // Rotates freely about the anchor
+    Ball,
+    // Slider, moves along axis. Limits are min/max offset from the anchor
+    Prismatic {
+        axis : [f32; 3],
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        limits : Option<[f32; 2]>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        motor : Option<MotorDefinition>, // Velocity in m/s, force in N
+    },
+    // Welds the bodies together
+    Fixed,
+}
+
+// Drives body1 relative to body2 about/along the joint's axis
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct MotorDefinition {
+    pub target_velocity : f32,
+    pub max_force : f32, // The most the motor can push with, it won't reach the target velocity against anything heavier
+    #[serde(default = "default_enabled")]
+    pub enabled : bool, // Whether the motor starts switched on
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+// Region of the level which pushes dynamic bodies around, e.g. fans and booster pads
+// Bodies are affected while their center of mass is inside
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct ForceZoneDefinition {
+    pub shape : ZoneShape,
+    pub position : [f32; 3], // Center of the zone
+    #[serde(default)]
+    pub rotation : [f32; 3], // Axis-angle, as for primitives
+    pub field : ForceField,
+    #[serde(default)]
+    pub falloff : Falloff,
+}
+
+// Tagged with "type" in json, e.g. "shape": { "type": "Box", "half_extents": [1.0, 1.0, 1.0] }
+#[derive(Serialize,Deserialize,Debug,Clone)]
+#[serde(tag = "type")]
+pub enum ZoneShape {
+    Box { half_extents : [f32; 3] },
+    Sphere { radius : f32 },
+}
+
+// Forces are accelerations (m/s^2), so light and heavy bodies are pushed the same way, like gravity
+#[derive(Serialize,Deserialize,Debug,Clone)]
+#[serde(tag = "type")]
+pub enum ForceField {
+    // Same direction everywhere, in the zone's frame
+    Uniform { acceleration : [f32; 3] },
+    // Away from the zone's center, negative strength pulls towards it
+    Radial { strength : f32 },
+}
+
+// How the force fades from the zone's center (full strength) to its edge
+#[derive(Serialize,Deserialize,Debug,Clone,Copy,Default)]
+pub enum Falloff {
+    #[default]
+    None, // Full strength everywhere
+    Linear,
+    Quadratic,
+}
+
+// Water (or anything else) which floats bodies less dense than it, and slows down anything moving through it
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct FluidVolumeDefinition {
+    pub position : [f32; 3], // Center of the volume
+    pub half_extents : [f32; 3], // Axis aligned, the surface is the top face
+    pub density : f32, // Same units as primitive density, water is 1000
+    #[serde(default)]
+    pub linear_drag : f32, // Rate velocity decays at when fully submerged (1/s), 1 loses about 63% a second
+    #[serde(default)]
+    pub angular_drag : f32,
+}
+
+// Spring between two placed primitives, or a primitive and the world
+// Anchors are in world coordinates, as the level is placed
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct LevelSpringDefinition {
+    pub body1 : usize, // Index into primitives
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body2 : Option<usize>, // Index into primitives, attached to the world if not set
+    pub anchor1 : [f32; 3], // Where the spring attaches to body1
+    pub anchor2 : [f32; 3], // Where the spring attaches to body2
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rest_length : Option<f32>, // Distance between the anchors if not set
+    pub stiffness : f32, // N/m
+    #[serde(default)]
+    pub damping : f32, // N/(m/s)
+    #[serde(default)]
+    pub rope : bool, // Only pulls, goes slack when shorter than its rest length
+}
+
+// Simulation state serialisation
+#[derive(Serialize,Deserialize,Debug,Clone)]
+pub struct BodyState {
+    pub name : String,
+    pub position : [f32; 3],
+    pub rotation : [f32; 3], // Axis-angle, as in LevelPrimitiveDefinition
+    pub linear_velocity : [f32; 3],
+    pub angular_velocity : [f32; 3],
+    #[serde(default)]
+    pub sleeping : bool,
+    // Set for bodies spawned by entities, so they can be spawned again when a snapshot is restored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spawned_scale : Option<[f32; 3]>,
+}
+
+#[derive(Serialize,Deserialize,Debug)]
+pub struct SimulationResult {
+    pub level : String,
+    pub time : f32, // Simulated time, in seconds
+    pub timestep : f32,
+    // One per body, in the order they were added: the level's primitives, then its entities, then anything entities spawned
+    // Anything which failed to load is missing, so match bodies up by name rather than by position
+    pub bodies : Vec<BodyState>,
+}
+
+// Full state of a running simulation, can be restored to resume from the same point
+#[derive(Serialize,Deserialize,Debug)]
+pub struct Snapshot {
+    pub level : String,
+    pub step_count : u64,
+    pub bodies : Vec<BodyState>, // One per body, in the order they were added, including any spawned by entities
+    #[serde(default)]
+    pub entities : Vec<EntityState>, // One per entity, in the order they were added
+    #[serde(default)]
+    pub motors : Vec<bool>, // Whether each motor is enabled
+}
+
+#[derive(Serialize,Deserialize,Debug)]
+pub struct EntityState {
+    pub active : bool,
+    pub ammo : u32,
+    pub cooldown : f32,
+    #[serde(default)]
+    pub switched : Option<bool>,
+}
+
+// Recorded poses of every body over time, for replay
+#[derive(Serialize,Deserialize,Debug)]
+pub struct Trajectory {
+    pub level : String,
+    pub bodies : Vec<String>, // Primitive name of each body, in the order they were added
+    #[serde(default)]
+    pub scales : Vec<[f32; 3]>, // Scale of each body, to draw any spawned while recording
+    pub frames : Vec<TrajectoryFrame>,
+}
+#[derive(Serialize,Deserialize,Debug)]
+pub struct TrajectoryFrame {
+    pub time : f32,
+    // Per body - translation x,y,z, rotation quaternion i,j,k,w
+    pub poses : Vec<[f32; 7]>,
 }
 
 // Entities
+// Each type of entity carries its own parameters, tagged with "type" in json
+// e.g. "entity_type": { "type": "Cannon", "spawn_point": [...], ... }
 #[derive(Serialize,Deserialize,Debug)]
+#[serde(tag = "type")]
 pub enum EntityType {
-    Cannon
+    Cannon {
+        spawn_point : [f32; 3], // Relative to the entity
+        spawn_velocity : [f32; 3], // Velocity the projectile is launched at (m/s), relative to the entity
+        projectile : String, // Name of a primitive in primitives_hidden
+        #[serde(default = "default_scale")]
+        projectile_scale : [f32; 3],
+        ammo : u32,
+        fire_delay : f32, // Seconds between shots
+    },
+    // Switches the motor on one of the level's joints on and off with the entity
+    MotorSwitch {
+        #[serde(default)]
+        joint : Option<usize>, // Index into the level's joints, must be set per placement with a parameter override
+    },
+}
+
+fn default_scale() -> [f32; 3] {
+    [1.0; 3]
 }
 
 #[derive(Serialize,Deserialize,Debug)]
 pub struct EntityDefinition {
+    #[serde(default)]
+    pub format_version : u32, // See migration.rs, 0 if from before versioning
     pub name     : String,
     pub entity_type : EntityType,
     pub primitive : PrimitiveDefinition,
     pub active_default   : bool,
-    // TODO: Can't work out how to do polymorphism into json, so just store all attributes here ¯\_(ツ)_/¯
-    pub cannon_spawn_point : [f32;3],
-    pub cannon_spawn_force : [f32;3],
-    pub cannon_projectile_name : String,
-    pub cannon_projectile_scale : [f32;3],
-    pub cannon_ammo : u32,
-    pub cannon_fire_delay : f32,
 }
@@ -0,0 +1,574 @@
+extern crate nalgebra as na;
+use na::{Vector3, Isometry3};
+
+use ncollide3d::bounding_volume::BoundingVolume;
+use ncollide3d::query;
+use ncollide3d::shape::ShapeHandle;
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::engine::{entity_parameters, primitive_collider_shapes, primitive_has_trimesh, primitive_placement, primitive_scale};
+use crate::types::*;
+
+// Colliders overlapping by less than this are just touching, not a problem
+pub const PENETRATION_TOLERANCE : f32 = 0.01;
+// Dynamic bodies sunk into static ones by less than this are resting on them, they're eased back out
+pub const RESTING_TOLERANCE : f32 = 0.1;
+
+// Something placed in the level which has a body
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum LevelBody {
+    Primitive(usize), // Index into LevelDefinition::primitives
+    Entity(usize), // Index into LevelDefinition::entities
+}
+
+impl fmt::Display for LevelBody {
+    fn fmt( &self, f : &mut fmt::Formatter ) -> fmt::Result {
+        match self {
+            LevelBody::Primitive(index) => write!(f, "Primitive {}", index),
+            LevelBody::Entity(index) => write!(f, "Entity {}", index),
+        }
+    }
+}
+
+// Problems with a level which would stop it from running as intended
+// Indices are into LevelDefinition::primitives
+#[derive(Debug)]
+pub enum LevelIssue {
+    // Primitive isn't in the library, it won't be spawned
+    UnknownPrimitive { index : usize, name : String },
+    // Primitive is placed outside the ground
+    OutOfBounds { index : usize, name : String, position : [f32; 3] },
+    // Primitive has a triangle mesh collider but isn't static, it won't be spawned
+    DynamicTriMesh { index : usize, name : String },
+    // Colliders start out interpenetrating, they'll be thrown apart on the first step
+    Overlapping { first : LevelBody, second : LevelBody, depth : f32 },
+    // Joint can't be built, it will be skipped. Index is into LevelDefinition::joints
+    InvalidJoint { index : usize, message : String },
+    // Spring can't be built, it will be skipped. Index is into LevelDefinition::springs
+    InvalidSpring { index : usize, message : String },
+    // Force zone won't push anything. Index is into LevelDefinition::force_zones
+    InvalidForceZone { index : usize, message : String },
+    // Fluid won't float anything, or its drag speeds things up or stops them dead. Index is into LevelDefinition::fluids
+    InvalidFluid { index : usize, message : String },
+    // Entity isn't in the library, it won't be spawned. Index is into LevelDefinition::entities
+    UnknownEntity { index : usize, name : String },
+    // Entity's parameters are wrong, it won't be spawned or won't do anything
+    InvalidEntity { index : usize, name : String, message : String },
+    // World setting is saved and loaded but the viewer can't apply it yet. Name is the field in WorldSettings
+    UnsupportedSetting { name : String },
+}
+
+impl fmt::Display for LevelIssue {
+    fn fmt( &self, f : &mut fmt::Formatter ) -> fmt::Result {
+        match self {
+            LevelIssue::UnknownPrimitive { index, name } =>
+                write!(f, "Primitive {}: Unknown primitive {}", index, name),
+            LevelIssue::OutOfBounds { index, name, position } =>
+                write!(f, "Primitive {} ({}): Outside the ground at {:?}", index, name, position),
+            LevelIssue::DynamicTriMesh { index, name } =>
+                write!(f, "Primitive {} ({}): Has a triangle mesh collider so must be static", index, name),
+            LevelIssue::Overlapping { first, second, depth } =>
+                write!(f, "{} and {}: Overlapping by {}", first, second, depth),
+            LevelIssue::InvalidJoint { index, message } =>
+                write!(f, "Joint {}: {}", index, message),
+            LevelIssue::InvalidSpring { index, message } =>
+                write!(f, "Spring {}: {}", index, message),
+            LevelIssue::InvalidForceZone { index, message } =>
+                write!(f, "Force zone {}: {}", index, message),
+            LevelIssue::InvalidFluid { index, message } =>
+                write!(f, "Fluid {}: {}", index, message),
+            LevelIssue::UnknownEntity { index, name } =>
+                write!(f, "Entity {}: Unknown entity {}", index, name),
+            LevelIssue::InvalidEntity { index, name, message } =>
+                write!(f, "Entity {} ({}): {}", index, name, message),
+            LevelIssue::UnsupportedSetting { name } =>
+                write!(f, "World {}: Not supported yet, it has no effect", name),
+        }
+    }
+}
+
+// A body in the level, with what's needed to check it for overlaps
+struct PlacedBody {
+    body : LevelBody,
+    is_static : bool,
+    pose : Isometry3<f32>,
+    shapes : Vec<ShapeHandle<f32>>,
+}
+
+impl PlacedBody {
+    fn new( body : LevelBody, prim : &PrimitiveDefinition, def : &LevelPrimitiveDefinition ) -> Option<Self> {
+        let shapes = primitive_collider_shapes(prim, &primitive_scale(prim, def)).ok()?;
+        Some(PlacedBody {
+            body,
+            is_static : def.is_static,
+            pose : Isometry3::new(Vector3::from(def.position), Vector3::from(def.rotation)),
+            shapes,
+        })
+    }
+}
+
+// Check a level against the primitive library, without running it
+pub fn validate_level( level : &LevelDefinition, primitives_library : &HashMap<String, PrimitiveDefinition> ) -> Vec<LevelIssue> {
+    let mut issues = Vec::new();
+    let half_width = level.ground_dimensions[0] / 2.0;
+    let half_depth = level.ground_dimensions[1] / 2.0;
+
+    let mut placed = Vec::new();
+    for (index, def) in level.primitives.iter().enumerate() {
+        let prim = match primitives_library.get(&def.name) {
+            Some(x) => x,
+            None => {
+                issues.push(LevelIssue::UnknownPrimitive { index, name : def.name.clone() });
+                continue;
+            }
+        };
+
+        if def.position[0].abs() > half_width || def.position[2].abs() > half_depth {
+            issues.push(LevelIssue::OutOfBounds { index, name : def.name.clone(), position : def.position });
+        }
+
+        if !def.is_static && primitive_has_trimesh(prim) {
+            issues.push(LevelIssue::DynamicTriMesh { index, name : def.name.clone() });
+            continue;
+        }
+
+        placed.extend(PlacedBody::new(LevelBody::Primitive(index), prim, def));
+    }
+    for (i, first) in placed.iter().enumerate() {
+        for second in &placed[i + 1..] {
+            issues.extend(check_overlap(first, second));
+        }
+    }
+
+    for (index, joint) in level.joints.iter().enumerate() {
+        if let Err(message) = check_joint(level, joint) {
+            issues.push(LevelIssue::InvalidJoint { index, message });
+        }
+    }
+
+    for (index, spring) in level.springs.iter().enumerate() {
+        if let Err(message) = check_spring(level, spring) {
+            issues.push(LevelIssue::InvalidSpring { index, message });
+        }
+    }
+
+    for (index, zone) in level.force_zones.iter().enumerate() {
+        if let Err(message) = check_force_zone(zone) {
+            issues.push(LevelIssue::InvalidForceZone { index, message });
+        }
+    }
+
+    for (index, fluid) in level.fluids.iter().enumerate() {
+        if let Err(message) = check_fluid(level, fluid) {
+            issues.push(LevelIssue::InvalidFluid { index, message });
+        }
+    }
+
+    // kiss3d doesn't support coloured lights
+    if level.world.light_colour != WorldSettings::default().light_colour {
+        issues.push(LevelIssue::UnsupportedSetting { name : String::from("light_colour") });
+    }
+
+    issues
+}
+
+// Check a level's entities against the entity library, without running it
+// Overlaps between entities and the level's primitives are checked here too, as they need both libraries
+pub fn validate_entities( level : &LevelDefinition, primitives_library : &HashMap<String, PrimitiveDefinition>, entities_library : &HashMap<String, EntityDefinition> ) -> Vec<LevelIssue> {
+    let mut issues = Vec::new();
+    let mut placed = Vec::new();
+    for (index, def) in level.entities.iter().enumerate() {
+        let ent = match entities_library.get(&def.name) {
+            Some(x) => x,
+            None => {
+                issues.push(LevelIssue::UnknownEntity { index, name : def.name.clone() });
+                continue;
+            }
+        };
+        let result = entity_parameters(ent, def).map_err(|e| e.to_string()).and_then(|entity_type| match entity_type {
+            EntityType::MotorSwitch { joint } => check_motor_switch(level, joint),
+            EntityType::Cannon { .. } => Ok(()),
+        });
+        if let Err(message) = result {
+            issues.push(LevelIssue::InvalidEntity { index, name : def.name.clone(), message });
+        }
+        let placement = primitive_placement(&ent.primitive.name, def.position, def.rotation, def.is_static);
+        placed.extend(PlacedBody::new(LevelBody::Entity(index), &ent.primitive, &placement));
+    }
+
+    // Primitives with problems are reported by validate_level, any that would be spawned are checked against the entities
+    let primitives : Vec<PlacedBody> = level.primitives.iter().enumerate()
+        .filter_map(|(index, def)| {
+            let prim = primitives_library.get(&def.name)?;
+            if !def.is_static && primitive_has_trimesh(prim) {
+                return None;
+            }
+            PlacedBody::new(LevelBody::Primitive(index), prim, def)
+        })
+        .collect();
+    for (i, first) in placed.iter().enumerate() {
+        for second in placed[i + 1..].iter().chain(&primitives) {
+            issues.extend(check_overlap(first, second));
+        }
+    }
+    issues
+}
+
+// Overlapping bodies get pushed apart on the first step, only a problem if one of them gets thrown
+fn check_overlap( first : &PlacedBody, second : &PlacedBody ) -> Option<LevelIssue> {
+    let tolerance = match (first.is_static, second.is_static) {
+        // Static bodies never move, so they can overlap each other all they like
+        (true, true) => return None,
+        (false, false) => PENETRATION_TOLERANCE,
+        _ => RESTING_TOLERANCE,
+    };
+    let depth = penetration_depth(&first.pose, &first.shapes, &second.pose, &second.shapes)?;
+    if depth > tolerance {
+        Some(LevelIssue::Overlapping { first : first.body, second : second.body, depth })
+    } else {
+        None
+    }
+}
+
+// A motor switch must be pointed at one of the level's joints, which has a motor
+pub fn check_motor_switch( level : &LevelDefinition, joint : Option<usize> ) -> Result<(), String> {
+    let index = joint.ok_or_else(|| String::from("No joint set, add \"parameters\" : { \"joint\" : <index into joints> }"))?;
+    match level.joints.get(index).map(|j| &j.joint_type) {
+        Some(JointType::Revolute { motor : Some(_), .. }) | Some(JointType::Prismatic { motor : Some(_), .. }) => Ok(()),
+        Some(_) => Err(format!("Joint {} doesn't have a motor", index)),
+        None => Err(format!("Joint {} doesn't exist", index)),
+    }
+}
+
+fn check_force_zone( zone : &ForceZoneDefinition ) -> Result<(), String> {
+    match &zone.shape {
+        ZoneShape::Box { half_extents } => if half_extents.iter().any(|x| *x <= 0.0) {
+            return Err(String::from("Box half_extents must all be positive"));
+        },
+        ZoneShape::Sphere { radius } => if *radius <= 0.0 {
+            return Err(String::from("Sphere radius must be positive"));
+        },
+    }
+    if let ForceField::Radial { strength } = &zone.field {
+        if *strength == 0.0 {
+            return Err(String::from("Radial field has no strength"));
+        }
+    }
+    Ok(())
+}
+
+fn check_fluid( level : &LevelDefinition, fluid : &FluidVolumeDefinition ) -> Result<(), String> {
+    if fluid.half_extents.iter().any(|x| *x <= 0.0) {
+        return Err(String::from("half_extents must all be positive"));
+    }
+    if fluid.density <= 0.0 {
+        return Err(String::from("Density must be positive"));
+    }
+    if fluid.linear_drag < 0.0 || fluid.angular_drag < 0.0 {
+        return Err(String::from("Drag can't be negative"));
+    }
+    // Past this a body loses nearly all its speed in one step, whatever the drag is
+    let timestep = if level.world.timestep > 0.0 { level.world.timestep } else { DEFAULT_TIMESTEP };
+    if fluid.linear_drag.max(fluid.angular_drag) * timestep > 1.0 {
+        return Err(format!("Drag can't be more than {}, 1 / timestep", 1.0 / timestep));
+    }
+    Ok(())
+}
+
+fn check_spring( level : &LevelDefinition, spring : &LevelSpringDefinition ) -> Result<(), String> {
+    for body in std::iter::once(spring.body1).chain(spring.body2) {
+        if body >= level.primitives.len() {
+            return Err(format!("Body {} doesn't exist", body));
+        }
+    }
+    if Some(spring.body1) == spring.body2 {
+        return Err(String::from("Connects a body to itself"));
+    }
+    if spring.stiffness < 0.0 || spring.damping < 0.0 || spring.rest_length.is_some_and(|l| l < 0.0) {
+        return Err(String::from("Stiffness, damping and rest length can't be negative"));
+    }
+    Ok(())
+}
+
+fn check_joint( level : &LevelDefinition, joint : &LevelJointDefinition ) -> Result<(), String> {
+    for body in std::iter::once(joint.body1).chain(joint.body2) {
+        if body >= level.primitives.len() {
+            return Err(format!("Body {} doesn't exist", body));
+        }
+    }
+    if Some(joint.body1) == joint.body2 {
+        return Err(String::from("Connects a body to itself"));
+    }
+    match &joint.joint_type {
+        JointType::Revolute { axis, limits, motor } | JointType::Prismatic { axis, limits, motor } => {
+            if Vector3::from(*axis).norm() == 0.0 {
+                return Err(String::from("Axis has no length"));
+            }
+            if let Some(limits) = limits {
+                if limits[0] > limits[1] {
+                    return Err(String::from("Minimum limit is greater than the maximum"));
+                }
+            }
+            if let Some(motor) = motor {
+                if motor.max_force < 0.0 {
+                    return Err(String::from("Motor max_force is negative"));
+                }
+            }
+        },
+        JointType::Ball | JointType::Fixed => {},
+    }
+    Ok(())
+}
+
+// Deepest penetration between any pair of shapes of two bodies, None if they don't touch
+fn penetration_depth( first_pos : &Isometry3<f32>, first_shapes : &[ShapeHandle<f32>], second_pos : &Isometry3<f32>, second_shapes : &[ShapeHandle<f32>] ) -> Option<f32> {
+    let mut result : Option<f32> = None;
+    for first_shape in first_shapes {
+        let first_aabb = first_shape.aabb(first_pos);
+        for second_shape in second_shapes {
+            if !first_aabb.intersects(&second_shape.aabb(second_pos)) {
+                continue;
+            }
+            if let Some(contact) = query::contact(first_pos, &**first_shape, second_pos, &**second_shape, 0.0) {
+                result = Some(result.map_or(contact.depth, |d| d.max(contact.depth)));
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::engine::{load_entities_definitions, load_level_empty, load_primitive_definition, load_primitives_definitions};
+
+    struct Libraries {
+        primitives : HashMap<String, PrimitiveDefinition>,
+        entities : HashMap<String, EntityDefinition>,
+    }
+
+    // The shipped libraries, plus a primitive with a triangle mesh collider
+    fn libraries() -> Libraries {
+        let mut primitives = load_primitives_definitions("assets/").unwrap().0;
+        let mut trimesh = load_primitive_definition("assets/primitives/cubey-1x1.json").unwrap();
+        trimesh.name = String::from("cubey-trimesh");
+        trimesh.collider_def[0].collider_type = ColliderType::TriMesh;
+        primitives.insert(trimesh.name.clone(), trimesh);
+        Libraries { primitives, entities : load_entities_definitions("assets/").unwrap().0 }
+    }
+
+    fn check( level : &LevelDefinition ) -> Vec<LevelIssue> {
+        let libraries = libraries();
+        let mut issues = validate_level(level, &libraries.primitives);
+        issues.append(&mut validate_entities(level, &libraries.primitives, &libraries.entities));
+        issues
+    }
+
+    fn place( name : &str, position : [f32; 3], is_static : bool ) -> LevelPrimitiveDefinition {
+        primitive_placement(name, position, [0.0; 3], is_static)
+    }
+
+    fn entity( name : &str, position : [f32; 3], parameters : serde_json::Value ) -> LevelEntityDefinition {
+        LevelEntityDefinition {
+            name : String::from(name),
+            position,
+            rotation : [0.0; 3],
+            is_static : true,
+            active_default : None,
+            parameters : parameters.as_object().unwrap().clone(),
+        }
+    }
+
+    // One of everything, all set up properly. Cubes are 1m, placed by the middle of their base
+    fn clean_level() -> LevelDefinition {
+        let mut level = load_level_empty("clean").unwrap();
+        level.primitives = vec![
+            place("cubey-1x1", [0.0, 0.0, 0.0], false),
+            place("cubey-1x1", [5.0, 0.0, 0.0], true),
+            place("cubey-1x1", [5.0, 1.0, 0.0], false), // Resting on the one below
+            place("domino-2x4", [10.0, 0.0, 0.0], false),
+        ];
+        level.joints.push(LevelJointDefinition {
+            joint_type : JointType::Revolute {
+                axis : [0.0, 1.0, 0.0],
+                limits : Some([-45.0, 45.0]),
+                motor : Some(MotorDefinition { target_velocity : 1.0, max_force : 100.0, enabled : true }),
+            },
+            body1 : 3,
+            body2 : None,
+            anchor : [10.0, 1.0, 0.0],
+        });
+        level.springs.push(LevelSpringDefinition {
+            body1 : 0,
+            body2 : Some(2),
+            anchor1 : [0.0, 1.0, 0.0],
+            anchor2 : [5.0, 2.0, 0.0],
+            rest_length : None,
+            stiffness : 10.0,
+            damping : 1.0,
+            rope : false,
+        });
+        level.force_zones.push(ForceZoneDefinition {
+            shape : ZoneShape::Box { half_extents : [1.0, 1.0, 1.0] },
+            position : [0.0, 5.0, 10.0],
+            rotation : [0.0; 3],
+            field : ForceField::Uniform { acceleration : [0.0, 20.0, 0.0] },
+            falloff : Falloff::None,
+        });
+        level.fluids.push(FluidVolumeDefinition {
+            position : [-10.0, 1.0, -10.0],
+            half_extents : [2.0, 1.0, 2.0],
+            density : 1000.0,
+            linear_drag : 0.5,
+            angular_drag : 0.5,
+        });
+        level.entities = vec![
+            entity("Cannon-2m", [-20.0, 0.0, 0.0], json!({})),
+            entity("motor-switch", [20.0, 0.0, 20.0], json!({ "joint" : 0 })),
+        ];
+        level
+    }
+
+    #[test]
+    fn clean_level_has_no_issues() {
+        let issues = check(&clean_level());
+        assert!(issues.is_empty(), "{:?}", issues);
+    }
+
+    #[test]
+    fn unknown_primitives_and_entities() {
+        let mut level = clean_level();
+        level.primitives.push(place("no-such-primitive", [-5.0, 0.0, 0.0], false));
+        level.entities.push(entity("no-such-entity", [-5.0, 0.0, 5.0], json!({})));
+        let issues = check(&level);
+        assert!(matches!(issues.as_slice(), [
+            LevelIssue::UnknownPrimitive { index : 4, .. },
+            LevelIssue::UnknownEntity { index : 2, .. },
+        ]), "{:?}", issues);
+    }
+
+    #[test]
+    fn out_of_bounds() {
+        let mut level = clean_level();
+        level.primitives[0].position = [60.0, 0.0, 0.0];
+        let issues = check(&level);
+        assert!(matches!(issues.as_slice(), [LevelIssue::OutOfBounds { index : 0, .. }]), "{:?}", issues);
+    }
+
+    #[test]
+    fn dynamic_trimesh() {
+        let mut level = clean_level();
+        level.primitives.push(place("cubey-trimesh", [-5.0, 0.0, 0.0], false));
+        level.primitives.push(place("cubey-trimesh", [-8.0, 0.0, 0.0], true));
+        let issues = check(&level);
+        assert!(matches!(issues.as_slice(), [LevelIssue::DynamicTriMesh { index : 4, .. }]), "{:?}", issues);
+    }
+
+    #[test]
+    fn overlapping_dynamic_bodies() {
+        let mut level = clean_level();
+        level.primitives.push(place("cubey-1x1", [0.5, 0.0, 0.0], false));
+        let issues = check(&level);
+        match issues.as_slice() {
+            [LevelIssue::Overlapping { first, second, depth }] => {
+                assert_eq!((*first, *second), (LevelBody::Primitive(0), LevelBody::Primitive(4)));
+                assert!((depth - 0.5).abs() < 1.0e-3, "{}", depth);
+            },
+            _ => panic!("{:?}", issues),
+        }
+    }
+
+    #[test]
+    fn resting_on_static_bodies_is_fine() {
+        let mut level = clean_level();
+        // Sunk in a little, and two static bodies inside each other
+        level.primitives[2].position[1] = 0.95;
+        level.primitives.push(place("cubey-1x1", [5.0, 0.0, 0.5], true));
+        assert!(check(&level).is_empty());
+
+        // Sunk in a long way
+        level.primitives[2].position[1] = 0.5;
+        let issues = check(&level);
+        assert!(matches!(issues.as_slice(), [
+            LevelIssue::Overlapping { first : LevelBody::Primitive(1), second : LevelBody::Primitive(2), .. },
+            LevelIssue::Overlapping { first : LevelBody::Primitive(2), second : LevelBody::Primitive(4), .. },
+        ]), "{:?}", issues);
+    }
+
+    #[test]
+    fn overlapping_entities() {
+        let mut level = clean_level();
+        level.primitives.push(place("cubey-1x1", [-20.0, 0.0, 0.0], false));
+        level.entities.push(entity("Cannon-2m", [-20.0, 0.0, 0.0], json!({})));
+        let issues = check(&level);
+        assert!(matches!(issues.as_slice(), [
+            LevelIssue::Overlapping { first : LevelBody::Entity(0), second : LevelBody::Primitive(4), .. },
+            LevelIssue::Overlapping { first : LevelBody::Entity(2), second : LevelBody::Primitive(4), .. },
+        ]), "{:?}", issues);
+    }
+
+    #[test]
+    fn invalid_joints_and_springs() {
+        let mut level = clean_level();
+        level.joints[0].body1 = 99;
+        level.springs[0].stiffness = -1.0;
+        let issues = check(&level);
+        assert!(matches!(issues.as_slice(), [
+            LevelIssue::InvalidJoint { index : 0, .. },
+            LevelIssue::InvalidSpring { index : 0, .. },
+        ]), "{:?}", issues);
+    }
+
+    #[test]
+    fn invalid_zones_and_fluids() {
+        let mut level = clean_level();
+        level.force_zones[0].shape = ZoneShape::Sphere { radius : 0.0 };
+        level.fluids[0].density = 0.0;
+        let issues = check(&level);
+        assert!(matches!(issues.as_slice(), [
+            LevelIssue::InvalidForceZone { index : 0, .. },
+            LevelIssue::InvalidFluid { index : 0, .. },
+        ]), "{:?}", issues);
+    }
+
+    #[test]
+    fn negative_or_excessive_drag() {
+        let mut level = clean_level();
+        level.fluids.push(level.fluids[0].clone());
+        level.fluids[0].linear_drag = -0.1;
+        level.fluids[1].angular_drag = 61.0;
+        let issues = check(&level);
+        assert!(matches!(issues.as_slice(), [
+            LevelIssue::InvalidFluid { index : 0, .. },
+            LevelIssue::InvalidFluid { index : 1, .. },
+        ]), "{:?}", issues);
+
+        // The limit follows the level's timestep
+        level.fluids.remove(0);
+        level.world.timestep = 1.0 / 120.0;
+        assert!(check(&level).is_empty());
+    }
+
+    #[test]
+    fn unsupported_settings() {
+        let mut level = clean_level();
+        level.world.light_colour = [1.0, 0.5, 0.5];
+        let issues = check(&level);
+        assert!(matches!(issues.as_slice(), [LevelIssue::UnsupportedSetting { name }] if name == "light_colour"), "{:?}", issues);
+    }
+
+    #[test]
+    fn invalid_entities() {
+        let mut level = clean_level();
+        level.entities[1].parameters.insert(String::from("joint"), json!(5));
+        level.entities[0].parameters.insert(String::from("no_such_parameter"), json!(1));
+        let issues = check(&level);
+        assert!(matches!(issues.as_slice(), [
+            LevelIssue::InvalidEntity { index : 0, .. },
+            LevelIssue::InvalidEntity { index : 1, .. },
+        ]), "{:?}", issues);
+    }
+}